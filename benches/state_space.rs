@@ -1,8 +1,120 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use alloy::{
+    primitives::{keccak256, Address, LogData, U256},
+    rpc::types::eth::Log,
+};
 use amms::{
-    amm::{uniswap_v2::UniswapV2Pool, AMM},
-    state_space::{cache::StateChangeCache, StateChange},
+    amm::{uniswap_v2::UniswapV2Pool, AutomatedMarketMaker, AMM},
+    errors::AMMError,
+    state_space::{
+        cache::StateChangeCache,
+        store::{StateSpaceBackend, StateSpaceStore},
+        StateChange, StateSpace,
+    },
 };
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tokio::sync::RwLock;
+
+const POOLS: u64 = 10_000;
+const LOGS_PER_BLOCK: u64 = 500;
+const READERS: usize = 4;
+
+fn pool_address(index: u64) -> Address {
+    Address::left_padding_from(&index.to_be_bytes())
+}
+
+fn state_space() -> StateSpace {
+    (0..POOLS)
+        .map(|i| {
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                address: pool_address(i),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<AMM>>()
+        .into()
+}
+
+fn sync_logs() -> Vec<Log> {
+    let signature = keccak256("Sync(uint112,uint112)");
+
+    (0..LOGS_PER_BLOCK)
+        .map(|i| {
+            let data = [U256::from(i + 1), U256::from(i + 2)]
+                .iter()
+                .flat_map(|word| word.to_be_bytes::<32>())
+                .collect::<Vec<u8>>();
+
+            Log {
+                inner: alloy::primitives::Log {
+                    address: pool_address(i * POOLS / LOGS_PER_BLOCK),
+                    data: LogData::new_unchecked(vec![signature], data.into()),
+                },
+                block_number: Some(1),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// The original state space, a `HashMap` behind a single lock taken for each log.
+#[derive(Clone)]
+struct HashMapStore(Arc<RwLock<HashMap<Address, AMM>>>);
+
+impl HashMapStore {
+    async fn sync_from_logs(&self, logs: Vec<Log>) -> Result<Vec<AMM>, AMMError> {
+        let mut prev_state = vec![];
+        for log in logs {
+            if let Some(amm) = self.0.write().await.get_mut(&log.address()) {
+                prev_state.push(amm.clone());
+                amm.sync_from_log(log)?;
+            }
+        }
+
+        Ok(prev_state)
+    }
+
+    async fn get(&self, address: &Address) -> Option<AMM> {
+        self.0.read().await.get(address).cloned()
+    }
+}
+
+/// A state space implementation under benchmark.
+#[derive(Clone)]
+enum BenchStore {
+    HashMap(HashMapStore),
+    Backend(StateSpaceStore),
+}
+
+impl BenchStore {
+    fn name(&self) -> String {
+        match self {
+            BenchStore::HashMap(_) => "Arc<RwLock<HashMap>>".to_string(),
+            BenchStore::Backend(store) => format!("{:?}", store.backend()),
+        }
+    }
+
+    async fn sync_from_logs(&self, logs: Vec<Log>) -> Result<usize, AMMError> {
+        match self {
+            BenchStore::HashMap(store) => store.sync_from_logs(logs).await.map(|amms| amms.len()),
+            BenchStore::Backend(store) => store.sync_from_logs(logs).await.map(|amms| amms.len()),
+        }
+    }
+
+    async fn get(&self, address: &Address) -> Option<AMM> {
+        match self {
+            BenchStore::HashMap(store) => store.get(address).await,
+            BenchStore::Backend(store) => store.get(address).await,
+        }
+    }
+}
 
 pub fn add_state_changes_benchmark(c: &mut Criterion) {
     let state_changes: Vec<StateChange> = (0..150)
@@ -32,5 +144,68 @@ pub fn add_state_changes_benchmark(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, add_state_changes_benchmark);
+pub fn state_space_backend_benchmark(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let logs = sync_logs();
+
+    // The original state space is the baseline for both backends
+    let stores = [
+        BenchStore::HashMap(HashMapStore(Arc::new(RwLock::new(
+            state_space().0.into_iter().collect(),
+        )))),
+        BenchStore::Backend(StateSpaceStore::new(
+            StateSpaceBackend::Locked,
+            state_space(),
+        )),
+        BenchStore::Backend(StateSpaceStore::new(
+            StateSpaceBackend::Sharded,
+            state_space(),
+        )),
+    ];
+
+    for store in stores {
+        let name = store.name();
+
+        c.bench_function(&format!("sync block from logs ({name})"), |b| {
+            b.to_async(&runtime)
+                .iter(|| store.sync_from_logs(black_box(logs.clone())));
+        });
+
+        // Readers continuously read pools while the block is applied
+        let reading = Arc::new(AtomicBool::new(true));
+        let readers = (0..READERS)
+            .map(|reader| {
+                let store = store.clone();
+                let reading = reading.clone();
+                runtime.spawn(async move {
+                    let mut i = reader as u64;
+                    while reading.load(Ordering::Relaxed) {
+                        black_box(store.get(&pool_address(i % POOLS)).await);
+                        i += READERS as u64;
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        c.bench_function(
+            &format!("sync block from logs with {READERS} readers ({name})"),
+            |b| {
+                b.to_async(&runtime)
+                    .iter(|| store.sync_from_logs(black_box(logs.clone())));
+            },
+        );
+
+        reading.store(false, Ordering::Relaxed);
+        for reader in readers {
+            runtime.block_on(reader).unwrap();
+        }
+    }
+}
+
+criterion_group!(
+    benches,
+    add_state_changes_benchmark,
+    state_space_backend_benchmark
+);
 criterion_main!(benches);
@@ -57,7 +57,7 @@ contract GetERC4626VaultDataBatchRequest {
             (bool assetTokenDecimalsSuccess, bytes memory assetTokenDecimalsData) =
                 assetToken.call{gas: 20000}(abi.encodeWithSignature("decimals()"));
 
-            if (!assetTokenDecimalsSuccess || assetTokenDecimalsData.length == 32) {
+            if (!assetTokenDecimalsSuccess || assetTokenDecimalsData.length != 32) {
                 continue;
             }
 
@@ -11,9 +11,13 @@ async fn main() -> eyre::Result<()> {
     let provider = ProviderBuilder::new().on_http(rpc_endpoint.parse()?);
 
     // discover vaults
-    let vaults = discovery::erc_4626::discover_erc_4626_vaults(provider, 30000).await?;
+    let discovery = discovery::erc_4626::discover_erc_4626_vaults(provider, 0, 30000).await?;
 
-    println!("Vaults: {:?}", vaults);
+    println!("Vaults: {:?}", discovery.vaults);
+
+    for rejected in discovery.rejected {
+        println!("Rejected {}: {}", rejected.address, rejected.reason);
+    }
 
     Ok(())
 }
@@ -0,0 +1,49 @@
+use alloy::{providers::ProviderBuilder, rpc::client::WsConnect};
+
+use amms::{
+    chains,
+    state_space::{
+        multi_chain::{ChainConfig, MultiChainStateSpace},
+        StateSpaceManager,
+    },
+    sync,
+};
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let endpoints = [
+        (chains::ETHEREUM_CHAIN_ID, "ETHEREUM_WS_ENDPOINT"),
+        (chains::BASE_CHAIN_ID, "BASE_WS_ENDPOINT"),
+    ];
+
+    let mut state_space = MultiChainStateSpace::new();
+    for (chain_id, endpoint) in endpoints {
+        let ws = WsConnect::new(std::env::var(endpoint)?);
+        let provider = ProviderBuilder::new().on_ws(ws).await?;
+
+        // Sync the pools of the known factories of the chain
+        let factories = chains::factories(chain_id);
+        let (amms, last_synced_block) =
+            sync::sync_amms(factories.clone(), provider.clone(), None, 1000).await?;
+
+        let manager = StateSpaceManager::new(amms, provider).with_factories(factories);
+        let config = ChainConfig::new(chain_id).with_reorg_depth(10);
+        state_space = state_space.with_chain(config, manager, last_synced_block)?;
+    }
+
+    // Subscribe before starting to receive every update
+    let mut updates_rx = state_space.subscribe();
+    let _join_handles = state_space.start(100).await?;
+
+    while let Ok(update) = updates_rx.recv().await {
+        for address in update.update.addresses() {
+            if let Some(amm) = state_space.get((update.chain_id, address)).await {
+                println!("Chain {}: {:?}", update.chain_id, amm);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,5 +1,7 @@
 use alloy::{primitives::address, providers::ProviderBuilder, rpc::client::WsConnect};
 
+use tokio::sync::broadcast::error::RecvError;
+
 use amms::{
     amm::{factory::Factory, uniswap_v2::factory::UniswapV2Factory, AMM},
     discovery,
@@ -37,28 +39,50 @@ async fn main() -> eyre::Result<()> {
 
     // Sync amms
     let (mut amms, last_synced_block) =
-        sync::sync_amms(factories, provider.clone(), None, step).await?;
+        sync::sync_amms(factories.clone(), provider.clone(), None, step).await?;
 
     // Discover vaults and add them to amms
-    let vaults = discovery::erc_4626::discover_erc_4626_vaults(provider.clone(), step)
+    let vaults = discovery::erc_4626::discover_erc_4626_vaults(provider.clone(), 0, step)
         .await?
+        .vaults
         .into_iter()
         .map(AMM::ERC4626Vault)
         .collect::<Vec<AMM>>();
 
     amms.extend(vaults);
 
-    // Initialize state space manager
-    let state_space_manager = StateSpaceManager::new(amms, provider);
+    // Initialize state space manager, adding pools created by the factories as they are deployed
+    let state_space_manager = StateSpaceManager::new(amms, provider).with_factories(factories);
+
+    // Subscribe before starting the manager to receive every update
+    let mut router_rx = state_space_manager.subscribe();
+    let mut metrics_rx = state_space_manager.subscribe();
 
-    //Listen for state changes and print them out
-    let (mut rx, _join_handles) = state_space_manager
-        .subscribe_state_changes(last_synced_block, 100)
-        .await?;
+    let _join_handles = state_space_manager.start(last_synced_block, 100).await?;
+
+    // Each subscriber receives every update independently
+    tokio::spawn(async move {
+        while let Ok(update) = metrics_rx.recv().await {
+            println!(
+                "Block {}: {} AMMs updated",
+                update.block_number,
+                update.updated.len()
+            );
+        }
+    });
 
     for _ in 0..10 {
-        if let Some(state_changes) = rx.recv().await {
-            println!("State changes: {:?}", state_changes);
+        match router_rx.recv().await {
+            Ok(update) => println!(
+                "Block {}: {:?}",
+                update.block_number,
+                update.addresses().collect::<Vec<_>>()
+            ),
+            Err(RecvError::Lagged(skipped)) => {
+                println!("Missed {skipped} updates, re-reading the state space");
+                let _state = state_space_manager.state();
+            }
+            Err(RecvError::Closed) => break,
         }
     }
 
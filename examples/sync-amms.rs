@@ -1,11 +1,9 @@
-use alloy::{primitives::address, providers::ProviderBuilder};
+use alloy::{providers::ProviderBuilder, rpc::client::ClientBuilder};
 
 use amms::{
-    amm::{
-        factory::Factory, uniswap_v2::factory::UniswapV2Factory,
-        uniswap_v3::factory::UniswapV3Factory,
-    },
-    sync,
+    chains,
+    rpc::limiter::{RequestLimiter, RequestLimiterConfig},
+    sync::{self, events::with_sync_events},
 };
 
 #[tokio::main]
@@ -14,30 +12,28 @@ async fn main() -> eyre::Result<()> {
 
     // Add rpc endpoint here:
     let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
-    let provider = ProviderBuilder::new().on_http(rpc_endpoint.parse()?);
-
-    let factories = vec![
-        // Add UniswapV2
-        Factory::UniswapV2Factory(UniswapV2Factory::new(
-            address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
-            2638438,
-            300,
-        )),
-        // Add Sushiswap
-        Factory::UniswapV2Factory(UniswapV2Factory::new(
-            address!("C0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"),
-            10794229,
-            300,
-        )),
-        // Add UniswapV3
-        Factory::UniswapV3Factory(UniswapV3Factory::new(
-            address!("1F98431c8aD98523631AE4a59f267346ea31F984"),
-            185,
-        )),
-    ];
+
+    // Limit the number of in flight requests, requests per second and compute units per second
+    // for every call made through the provider
+    let limiter = RequestLimiter::new(RequestLimiterConfig::new(32, Some(100), Some(660)));
+    let client = ClientBuilder::default()
+        .layer(limiter.layer())
+        .http(rpc_endpoint.parse()?);
+    let provider = ProviderBuilder::new().on_client(client);
+
+    // Uniswap V2, SushiSwap and Uniswap V3 on Ethereum mainnet
+    let factories = chains::factories(chains::ETHEREUM_CHAIN_ID);
+
+    // Log sync progress as it is reported
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(event) = events_rx.recv().await {
+            tracing::info!(?event, "Sync progress");
+        }
+    });
 
     // Sync pairs
-    sync::sync_amms(factories, provider, None, 500).await?;
+    with_sync_events(events_tx, sync::sync_amms(factories, provider, None, 500)).await?;
 
     Ok(())
 }
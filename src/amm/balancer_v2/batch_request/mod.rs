@@ -57,7 +57,11 @@ where
     Ok(())
 }
 
-pub async fn get_amm_data_batch_request<N, P>(amms: &mut [AMM], provider: P) -> Result<(), AMMError>
+pub async fn get_amm_data_batch_request<N, P>(
+    amms: &mut [AMM],
+    block_number: Option<u64>,
+    provider: P,
+) -> Result<(), AMMError>
 where
     N: Network,
     P: Provider<N> + Clone,
@@ -66,7 +70,11 @@ where
         provider,
         amms.iter().map(|amm| amm.address()).collect(),
     );
-    let res = deployer.call_raw().await?;
+    let res = if let Some(block_number) = block_number {
+        deployer.block(block_number.into()).call_raw().await?
+    } else {
+        deployer.call_raw().await?
+    };
 
     let pools = <Vec<(Vec<Address>, Vec<u16>, Vec<U256>, Vec<U256>, u32)> as SolValue>::abi_decode(
         &res, false,
@@ -7,15 +7,16 @@ use alloy::{
     sol_types::SolEvent,
 };
 use async_trait::async_trait;
-use futures::{stream::FuturesUnordered, StreamExt};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     amm::{factory::AutomatedMarketMakerFactory, AutomatedMarketMaker, AMM},
     errors::AMMError,
+    rpc::get_logs::{get_logs_in_chunks, LogRangeConfig},
+    sync::batch,
 };
 
-use super::{batch_request, BalancerV2Pool};
+use super::BalancerV2Pool;
 
 sol! {
     #[derive(Debug, PartialEq, Eq)]
@@ -62,19 +63,14 @@ impl AutomatedMarketMakerFactory for BalancerV2Factory {
     async fn populate_amm_data<N, P>(
         &self,
         amms: &mut [AMM],
-        _block_number: Option<u64>,
+        block_number: Option<u64>,
         provider: P,
     ) -> Result<(), AMMError>
     where
         N: Network,
         P: Provider<N> + Clone,
     {
-        // Max batch size for call
-        let step = 127;
-        for amm_chunk in amms.chunks_mut(step) {
-            batch_request::get_amm_data_batch_request(amm_chunk, provider.clone()).await?;
-        }
-        Ok(())
+        batch::populate_in_chunks(amms, block_number, provider).await
     }
 
     /// Returns the creation event signature for the factory.
@@ -123,40 +119,20 @@ impl BalancerV2Factory {
         N: Network,
         P: Provider<N> + Clone,
     {
-        // Unwrap can be used here because the creation block was verified within `Dex::new()`
-        let mut from_block = self.creation_block;
-
-        let mut futures = FuturesUnordered::new();
-        let mut amms = vec![];
-        while from_block < to_block {
-            let provider = provider.clone();
-
-            let mut target_block = from_block + step - 1;
-            if target_block > to_block {
-                target_block = to_block;
-            }
+        let filter = Filter::new().event_signature(vec![IBFactory::LOG_NEW_POOL::SIGNATURE_HASH]);
 
-            futures.push(async move {
-                provider
-                    .get_logs(
-                        &Filter::new()
-                            .event_signature(vec![IBFactory::LOG_NEW_POOL::SIGNATURE_HASH])
-                            .from_block(from_block)
-                            .to_block(target_block),
-                    )
-                    .await
-            });
+        let logs = get_logs_in_chunks(
+            &filter,
+            self.creation_block,
+            to_block,
+            LogRangeConfig::new(step),
+            provider.clone(),
+        )
+        .await?;
 
-            from_block += step;
-        }
-
-        // TODO: this could be more dry since we use this in another place
-        while let Some(result) = futures.next().await {
-            let logs = result.map_err(AMMError::TransportError)?;
-
-            for log in logs {
-                amms.push(self.new_amm_from_log(log, provider.clone()).await?);
-            }
+        let mut amms = vec![];
+        for log in logs {
+            amms.push(self.new_amm_from_log(log, provider.clone()).await?);
         }
 
         Ok(amms)
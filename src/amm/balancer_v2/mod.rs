@@ -2,6 +2,7 @@ pub mod batch_request;
 pub mod bmath;
 pub mod error;
 pub mod factory;
+pub mod vault;
 
 use alloy::{
     network::Network,
@@ -0,0 +1,523 @@
+use alloy::{
+    network::Network,
+    primitives::{Address, Bytes, B256, I256, U256},
+    providers::Provider,
+    rpc::types::Log,
+    sol,
+    sol_types::{SolCall, SolEvent},
+};
+use async_trait::async_trait;
+use rug::{float::Round, Float};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    amm::{
+        consts::{BONE, MPFR_T_PRECISION},
+        AutomatedMarketMaker, IErc20, AMM,
+    },
+    discovery::balancer_v2::BALANCER_V2_VAULT,
+    errors::{AMMError, ArithmeticError, EventLogError, SwapSimulationError},
+    rpc::multicall,
+};
+
+use super::bmath::{self, u256_to_float};
+
+/// Number of calls made to populate each pool, before the calls for its token decimals.
+const POOL_CALLS: usize = 3;
+
+sol! {
+    /// Functions and events of the Balancer V2 Vault used to track the balances of its pools
+    #[derive(Debug, PartialEq, Eq)]
+    #[sol(rpc)]
+    contract IBalancerV2VaultPools {
+        event Swap(bytes32 indexed poolId, address indexed tokenIn, address indexed tokenOut, uint256 amountIn, uint256 amountOut);
+        event PoolBalanceChanged(bytes32 indexed poolId, address indexed liquidityProvider, address[] tokens, int256[] deltas, uint256[] protocolFeeAmounts);
+        function getPoolTokens(bytes32 poolId) external view returns (address[] memory tokens, uint256[] memory balances, uint256 lastChangeBlock);
+    }
+}
+
+sol! {
+    /// Interface of a Balancer V2 weighted pool
+    #[derive(Debug, PartialEq, Eq)]
+    #[sol(rpc)]
+    contract IBalancerV2WeightedPool {
+        function getNormalizedWeights() external view returns (uint256[] memory);
+        function getSwapFeePercentage() external view returns (uint256);
+    }
+}
+
+/// A Balancer V2 weighted pool, whose balances are held by the Balancer V2 Vault.
+///
+/// Balances are synced from the `Swap` and `PoolBalanceChanged` events of the Vault. Balances
+/// moved by an asset manager and changes to the weights or swap fee are only picked up by
+/// [`sync`](AutomatedMarketMaker::sync).
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct BalancerV2VaultPool {
+    pub address: Address,
+    /// Id of the pool in the Vault, whose first 20 bytes are the pool address
+    pub pool_id: B256,
+    /// Tokens of the pool, in Vault order
+    pub tokens: Vec<Address>,
+    pub decimals: Vec<u8>,
+    /// Balances held by the Vault for the pool, indexed by token
+    pub balances: Vec<U256>,
+    /// Normalized weights indexed by token, summing to `BONE`
+    pub weights: Vec<U256>,
+    /// Swap fee scaled by `BONE`
+    pub fee: U256,
+}
+
+#[async_trait]
+impl AutomatedMarketMaker for BalancerV2VaultPool {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    #[instrument(skip(self, provider), level = "debug")]
+    async fn sync<N, P>(&mut self, provider: P) -> Result<(), AMMError>
+    where
+        N: Network,
+        P: Provider<N> + Clone,
+    {
+        self.populate_data(None, provider).await
+    }
+
+    fn sync_on_event_signatures(&self) -> Vec<B256> {
+        vec![
+            IBalancerV2VaultPools::Swap::SIGNATURE_HASH,
+            IBalancerV2VaultPools::PoolBalanceChanged::SIGNATURE_HASH,
+        ]
+    }
+
+    fn tokens(&self) -> Vec<Address> {
+        self.tokens.clone()
+    }
+
+    fn decimals(&self) -> Vec<u8> {
+        self.decimals.clone()
+    }
+
+    /// Calculates the spot price of `base_token` in `quote_token`, fee inclusive.
+    fn calculate_price(&self, base_token: Address, quote_token: Address) -> Result<f64, AMMError> {
+        let base_index = self.token_index(base_token, ArithmeticError::BaseTokenDoesNotExist)?;
+        let quote_index = self.token_index(quote_token, ArithmeticError::QuoteTokenDoesNotExist)?;
+
+        let scale = |index: usize| {
+            Float::with_val(MPFR_T_PRECISION, 10_u64.pow(self.decimals[index] as u32))
+        };
+        let balance_base = u256_to_float(self.balances[base_index]) / scale(base_index);
+        let balance_quote = u256_to_float(self.balances[quote_index]) / scale(quote_index);
+
+        let bone = u256_to_float(BONE);
+        let dividend = (balance_quote / u256_to_float(self.weights[quote_index])) * bone.clone();
+        let divisor = (balance_base / u256_to_float(self.weights[base_index]))
+            * (bone - u256_to_float(self.fee));
+        let ratio = dividend / divisor;
+        Ok(ratio.to_f64_round(Round::Nearest))
+    }
+
+    #[instrument(skip(self), level = "debug")]
+    fn sync_from_log(&mut self, log: Log) -> Result<(), AMMError> {
+        let signature = log.topics()[0];
+
+        if IBalancerV2VaultPools::Swap::SIGNATURE_HASH == signature {
+            let swap_event = IBalancerV2VaultPools::Swap::decode_log(log.as_ref(), true)?;
+            if swap_event.poolId != self.pool_id {
+                return Ok(());
+            }
+
+            let token_in_index = self.log_token_index(swap_event.tokenIn)?;
+            let token_out_index = self.log_token_index(swap_event.tokenOut)?;
+
+            self.balances[token_in_index] += swap_event.amountIn;
+            self.balances[token_out_index] -= swap_event.amountOut;
+
+            tracing::debug!(?swap_event, address = ?self.address, balances = ?self.balances);
+        } else if IBalancerV2VaultPools::PoolBalanceChanged::SIGNATURE_HASH == signature {
+            let balance_event =
+                IBalancerV2VaultPools::PoolBalanceChanged::decode_log(log.as_ref(), true)?;
+            if balance_event.poolId != self.pool_id {
+                return Ok(());
+            }
+
+            for ((token, delta), protocol_fee) in balance_event
+                .tokens
+                .iter()
+                .zip(balance_event.deltas.iter())
+                .zip(balance_event.protocolFeeAmounts.iter())
+            {
+                let index = self.log_token_index(*token)?;
+                let balance =
+                    I256::from_raw(self.balances[index]) + *delta - I256::from_raw(*protocol_fee);
+                self.balances[index] = balance.into_raw();
+            }
+
+            tracing::debug!(?balance_event, address = ?self.address, balances = ?self.balances);
+        } else {
+            return Err(AMMError::from(EventLogError::InvalidEventSignature));
+        }
+
+        Ok(())
+    }
+
+    async fn populate_data<N, P>(
+        &mut self,
+        block_number: Option<u64>,
+        provider: P,
+    ) -> Result<(), AMMError>
+    where
+        N: Network,
+        P: Provider<N> + Clone,
+    {
+        let mut amms = [AMM::BalancerV2VaultPool(self.clone())];
+        get_amm_data_batch_request(&mut amms, block_number, provider).await?;
+
+        let [AMM::BalancerV2VaultPool(pool)] = amms else {
+            unreachable!("The pool variant is not changed by the batch request");
+        };
+        *self = pool;
+
+        Ok(())
+    }
+
+    /// Simulates a swap with the weighted math of the pool.
+    ///
+    /// The Vault computes swaps on balances upscaled to 18 decimals with its own fixed point
+    /// power function, so the amount received may differ from the on-chain amount by rounding.
+    fn simulate_swap(
+        &self,
+        base_token: Address,
+        quote_token: Address,
+        amount_in: U256,
+    ) -> Result<U256, AMMError> {
+        let base_index =
+            self.swap_token_index(base_token, ArithmeticError::BaseTokenDoesNotExist)?;
+        let quote_index =
+            self.swap_token_index(quote_token, ArithmeticError::QuoteTokenDoesNotExist)?;
+
+        Ok(bmath::calculate_out_given_in(
+            self.balances[base_index],
+            self.weights[base_index],
+            self.balances[quote_index],
+            self.weights[quote_index],
+            amount_in,
+            self.fee,
+        )?)
+    }
+
+    fn simulate_swap_mut(
+        &mut self,
+        base_token: Address,
+        quote_token: Address,
+        amount_in: U256,
+    ) -> Result<U256, AMMError> {
+        let amount_out = self.simulate_swap(base_token, quote_token, amount_in)?;
+
+        let base_index =
+            self.swap_token_index(base_token, ArithmeticError::BaseTokenDoesNotExist)?;
+        let quote_index =
+            self.swap_token_index(quote_token, ArithmeticError::QuoteTokenDoesNotExist)?;
+        self.balances[base_index] = bmath::badd(self.balances[base_index], amount_in)?;
+        self.balances[quote_index] = bmath::bsub(self.balances[quote_index], amount_out)?;
+
+        Ok(amount_out)
+    }
+}
+
+impl BalancerV2VaultPool {
+    /// Creates an unpopulated pool from its Vault registration.
+    pub fn new(address: Address, pool_id: B256, tokens: Vec<Address>) -> BalancerV2VaultPool {
+        BalancerV2VaultPool {
+            address,
+            pool_id,
+            tokens,
+            ..Default::default()
+        }
+    }
+
+    fn token_index(&self, token: Address, err: ArithmeticError) -> Result<usize, ArithmeticError> {
+        self.tokens.iter().position(|&t| t == token).ok_or(err)
+    }
+
+    fn swap_token_index(
+        &self,
+        token: Address,
+        err: ArithmeticError,
+    ) -> Result<usize, SwapSimulationError> {
+        Ok(self.token_index(token, err)?)
+    }
+
+    fn log_token_index(&self, token: Address) -> Result<usize, AMMError> {
+        self.tokens
+            .iter()
+            .position(|&t| t == token)
+            .ok_or(AMMError::SyncError(self.address))
+    }
+}
+
+/// Returns the address of the pool a Balancer V2 Vault log applies to, or `None` if the log was
+/// not emitted by the Vault.
+///
+/// The Vault emits the events of all its pools, keyed by the pool id whose first 20 bytes are the
+/// address of the pool.
+pub fn vault_log_pool_address(log: &Log) -> Option<Address> {
+    if log.address() != BALANCER_V2_VAULT {
+        return None;
+    }
+
+    let pool_id = log.topics().get(1)?;
+    Some(Address::from_slice(&pool_id[..20]))
+}
+
+/// Populates Balancer V2 Vault pools with two batched multicalls, one for the pool tokens,
+/// balances, weights and fees and one for the decimals of their tokens.
+pub async fn get_amm_data_batch_request<N, P>(
+    amms: &mut [AMM],
+    block_number: Option<u64>,
+    provider: P,
+) -> Result<(), AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let mut calls = Vec::with_capacity(amms.len() * POOL_CALLS);
+    for amm in amms.iter() {
+        let AMM::BalancerV2VaultPool(pool) = amm else {
+            return Err(AMMError::IncongruentAMMs);
+        };
+
+        calls.extend(pool_calls(pool));
+    }
+
+    let return_data = multicall::try_aggregate(calls, block_number, provider.clone()).await?;
+
+    for (amm, return_data) in amms.iter_mut().zip(return_data.chunks_exact(POOL_CALLS)) {
+        let AMM::BalancerV2VaultPool(pool) = amm else {
+            unreachable!("AMMs are checked to be Balancer V2 Vault pools");
+        };
+
+        populate_pool(pool, return_data)?;
+    }
+
+    let decimal_calls = amms
+        .iter()
+        .flat_map(|amm| amm.tokens())
+        .map(|token| (token, Bytes::from(IErc20::decimalsCall {}.abi_encode())))
+        .collect();
+    let mut decimals = multicall::try_aggregate(decimal_calls, block_number, provider)
+        .await?
+        .into_iter();
+
+    for amm in amms.iter_mut() {
+        let AMM::BalancerV2VaultPool(pool) = amm else {
+            unreachable!("AMMs are checked to be Balancer V2 Vault pools");
+        };
+
+        pool.decimals = pool
+            .tokens
+            .iter()
+            .map(|_| {
+                decimals
+                    .next()
+                    .flatten()
+                    .and_then(|data| IErc20::decimalsCall::abi_decode_returns(&data, true).ok())
+                    .map(|decimals| decimals._0)
+                    .ok_or(AMMError::BatchRequestError(pool.address))
+            })
+            .collect::<Result<_, _>>()?;
+
+        tracing::trace!(?pool);
+    }
+
+    Ok(())
+}
+
+/// Returns the calls populating `pool`, in the order expected by [`populate_pool`].
+fn pool_calls(pool: &BalancerV2VaultPool) -> [(Address, Bytes); POOL_CALLS] {
+    [
+        (
+            BALANCER_V2_VAULT,
+            IBalancerV2VaultPools::getPoolTokensCall {
+                poolId: pool.pool_id,
+            }
+            .abi_encode()
+            .into(),
+        ),
+        (
+            pool.address,
+            IBalancerV2WeightedPool::getNormalizedWeightsCall {}
+                .abi_encode()
+                .into(),
+        ),
+        (
+            pool.address,
+            IBalancerV2WeightedPool::getSwapFeePercentageCall {}
+                .abi_encode()
+                .into(),
+        ),
+    ]
+}
+
+/// Populates the tokens, balances, weights and fee of `pool` from the results of [`pool_calls`].
+fn populate_pool(
+    pool: &mut BalancerV2VaultPool,
+    return_data: &[Option<Bytes>],
+) -> Result<(), AMMError> {
+    let [Some(pool_tokens), Some(weights), Some(fee)] = return_data else {
+        return Err(AMMError::BatchRequestError(pool.address));
+    };
+
+    let pool_tokens =
+        IBalancerV2VaultPools::getPoolTokensCall::abi_decode_returns(pool_tokens, true)?;
+    let weights =
+        IBalancerV2WeightedPool::getNormalizedWeightsCall::abi_decode_returns(weights, true)?;
+    let fee = IBalancerV2WeightedPool::getSwapFeePercentageCall::abi_decode_returns(fee, true)?;
+
+    if pool_tokens.tokens.len() != weights._0.len() {
+        return Err(AMMError::BatchRequestError(pool.address));
+    }
+
+    pool.tokens = pool_tokens.tokens;
+    pool.balances = pool_tokens.balances;
+    pool.weights = weights._0;
+    pool.fee = fee._0;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::sol_types::SolValue;
+
+    fn pool() -> BalancerV2VaultPool {
+        let address = Address::with_last_byte(1);
+        let mut pool_id = B256::ZERO;
+        pool_id[..20].copy_from_slice(address.as_slice());
+
+        BalancerV2VaultPool {
+            address,
+            pool_id,
+            tokens: vec![Address::with_last_byte(2), Address::with_last_byte(3)],
+            decimals: vec![18, 6],
+            balances: vec![
+                U256::from(1_000) * BONE,
+                U256::from(2_000_000) * U256::from(1_000_000),
+            ],
+            weights: vec![U256::from(8) * BONE / U256::from(10), BONE / U256::from(5)],
+            fee: BONE / U256::from(1_000),
+        }
+    }
+
+    fn vault_log<E: SolEvent>(event: &E) -> Log {
+        Log {
+            inner: alloy::primitives::Log {
+                address: BALANCER_V2_VAULT,
+                data: event.encode_log_data(),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_populate_pool() {
+        let mut pool =
+            BalancerV2VaultPool::new(Address::with_last_byte(1), B256::with_last_byte(1), vec![]);
+        let expected = self::pool();
+
+        let return_data = [
+            Some(Bytes::from(
+                (
+                    expected.tokens.clone(),
+                    expected.balances.clone(),
+                    U256::from(1),
+                )
+                    .abi_encode_params(),
+            )),
+            Some(Bytes::from(expected.weights.abi_encode())),
+            Some(Bytes::from(expected.fee.abi_encode())),
+        ];
+        populate_pool(&mut pool, &return_data).unwrap();
+
+        assert_eq!(pool.tokens, expected.tokens);
+        assert_eq!(pool.balances, expected.balances);
+        assert_eq!(pool.weights, expected.weights);
+        assert_eq!(pool.fee, expected.fee);
+
+        // Pools that are not weighted pools revert on `getNormalizedWeights`
+        let return_data = [return_data[0].clone(), None, return_data[2].clone()];
+        assert!(populate_pool(&mut pool, &return_data).is_err());
+    }
+
+    #[test]
+    fn test_sync_from_log() {
+        let mut pool = pool();
+        let [token_a, token_b] = [pool.tokens[0], pool.tokens[1]];
+        let [balance_a, balance_b] = [pool.balances[0], pool.balances[1]];
+
+        let log = vault_log(&IBalancerV2VaultPools::Swap {
+            poolId: pool.pool_id,
+            tokenIn: token_a,
+            tokenOut: token_b,
+            amountIn: U256::from(100),
+            amountOut: U256::from(50),
+        });
+        assert_eq!(vault_log_pool_address(&log), Some(pool.address));
+
+        pool.sync_from_log(log).unwrap();
+        assert_eq!(pool.balances[0], balance_a + U256::from(100));
+        assert_eq!(pool.balances[1], balance_b - U256::from(50));
+
+        // An exit of 10 token A charging a protocol fee of 1 and a join of 20 token B
+        pool.sync_from_log(vault_log(&IBalancerV2VaultPools::PoolBalanceChanged {
+            poolId: pool.pool_id,
+            liquidityProvider: Address::with_last_byte(4),
+            tokens: vec![token_a, token_b],
+            deltas: vec![
+                I256::try_from(-10_i64).unwrap(),
+                I256::try_from(20_i64).unwrap(),
+            ],
+            protocolFeeAmounts: vec![U256::from(1), U256::ZERO],
+        }))
+        .unwrap();
+        assert_eq!(pool.balances[0], balance_a + U256::from(89));
+        assert_eq!(pool.balances[1], balance_b - U256::from(30));
+
+        // Logs of other pools are ignored
+        pool.sync_from_log(vault_log(&IBalancerV2VaultPools::Swap {
+            poolId: B256::with_last_byte(2),
+            tokenIn: token_a,
+            tokenOut: token_b,
+            amountIn: U256::from(100),
+            amountOut: U256::from(50),
+        }))
+        .unwrap();
+        assert_eq!(pool.balances[0], balance_a + U256::from(89));
+    }
+
+    #[test]
+    fn test_simulate_swap() {
+        let mut pool = pool();
+        let [token_a, token_b] = [pool.tokens[0], pool.tokens[1]];
+
+        // 1 token A is worth about 8_000 token B in an 80/20 pool of 1_000 A and 2_000_000 B
+        let price = pool.calculate_price(token_a, token_b).unwrap();
+        assert!((price - 8_008.008).abs() < 0.01);
+
+        let amount_out = pool.simulate_swap(token_a, token_b, BONE).unwrap();
+        let expected = U256::from(7_972) * U256::from(1_000_000);
+        assert!(amount_out > expected && amount_out < expected + U256::from(1_000_000));
+
+        let balance_b = pool.balances[1];
+        assert_eq!(
+            pool.simulate_swap_mut(token_a, token_b, BONE).unwrap(),
+            amount_out
+        );
+        assert_eq!(pool.balances[1], balance_b - amount_out);
+
+        assert!(pool
+            .simulate_swap(Address::with_last_byte(4), token_b, BONE)
+            .is_err());
+    }
+}
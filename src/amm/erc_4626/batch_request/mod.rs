@@ -1,4 +1,7 @@
-use crate::{amm::AutomatedMarketMaker, errors::AMMError};
+use crate::{
+    amm::{AutomatedMarketMaker, AMM},
+    errors::AMMError,
+};
 
 use alloy::{
     network::Network,
@@ -8,7 +11,7 @@ use alloy::{
     sol_types::SolValue,
 };
 
-use super::ERC4626Vault;
+use super::{derive_fee, ERC4626Vault};
 
 sol! {
     #[allow(missing_docs)]
@@ -17,8 +20,26 @@ sol! {
     "src/amm/erc_4626/batch_request/GetERC4626VaultDataBatchRequestABI.json"
 }
 
+type VaultData = (
+    Address,
+    u16,
+    Address,
+    u16,
+    U256,
+    U256,
+    U256,
+    U256,
+    U256,
+    U256,
+    U256,
+    U256,
+);
+
+/// Populates `vault` at `block_number`, or at the latest block if `None`, with a single batch
+/// request.
 pub async fn get_4626_vault_data_batch_request<N, P>(
     vault: &mut ERC4626Vault,
+    block_number: Option<u64>,
     provider: P,
 ) -> Result<(), AMMError>
 where
@@ -27,22 +48,75 @@ where
 {
     let deployer =
         IGetERC4626VaultDataBatchRequest::deploy_builder(provider, vec![vault.vault_token]);
-    let res = deployer.call_raw().await?;
-
-    let data = <Vec<(
-        Address,
-        u16,
-        Address,
-        u16,
-        U256,
-        U256,
-        U256,
-        U256,
-        U256,
-        U256,
-        U256,
-        U256,
-    )> as SolValue>::abi_decode(&res, false)?;
+    let res = if let Some(block_number) = block_number {
+        deployer.block(block_number.into()).call_raw().await?
+    } else {
+        deployer.call_raw().await?
+    };
+
+    let data = <Vec<VaultData> as SolValue>::abi_decode(&res, false)?;
+    let vault_data = if !data.is_empty() {
+        data[0]
+    } else {
+        return Err(AMMError::BatchRequestError(vault.address()));
+    };
+
+    populate_vault_data(vault, vault_data)
+}
+
+/// Populates all vaults in `amms` with a single batch request.
+///
+/// Vaults skipped by the batch request or with an inconsistent fee are left unpopulated so that
+/// they are removed by [`filter_empty_amms`](crate::filters::filter_empty_amms).
+pub async fn get_amm_data_batch_request<N, P>(
+    amms: &mut [AMM],
+    block_number: Option<u64>,
+    provider: P,
+) -> Result<(), AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let deployer = IGetERC4626VaultDataBatchRequest::deploy_builder(
+        provider,
+        amms.iter().map(|amm| amm.address()).collect(),
+    );
+    let res = if let Some(block_number) = block_number {
+        deployer.block(block_number.into()).call_raw().await?
+    } else {
+        deployer.call_raw().await?
+    };
+
+    let vaults = <Vec<VaultData> as SolValue>::abi_decode(&res, false)?;
+
+    for (vault_idx, vault_data) in vaults.into_iter().enumerate() {
+        if let AMM::ERC4626Vault(vault) = amms
+            .get_mut(vault_idx)
+            .expect("Vault idx should be in bounds")
+        {
+            match populate_vault_data(vault, vault_data) {
+                Ok(()) => tracing::trace!(?vault),
+                Err(err) => {
+                    tracing::debug!(vault = ?vault.vault_token, ?err, "Skipping unpopulated vault")
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Populates the vault from the batch request data.
+///
+/// Leaves the vault untouched and returns [`AMMError::BatchRequestError`] if the batch request
+/// skipped the vault, e.g. because its asset has no valid decimals, or
+/// [`AMMError::InvalidERC4626Fee`] if either fee is inconsistent.
+fn populate_vault_data(vault: &mut ERC4626Vault, vault_data: VaultData) -> Result<(), AMMError> {
+    // Skipped vaults are returned zeroed
+    if vault_data.0.is_zero() {
+        return Err(AMMError::BatchRequestError(vault.vault_token));
+    }
+
     let (
         vault_token,
         vault_token_dec,
@@ -56,35 +130,10 @@ where
         withdraw_fee_delta_1,
         withdraw_fee_delta_2,
         withdraw_no_fee,
-    ) = if !data.is_empty() {
-        data[0]
-    } else {
-        return Err(AMMError::BatchRequestError(vault.address()));
-    };
-
-    // If both deltas are zero, the fee is zero
-    if deposit_fee_delta_1.is_zero() && deposit_fee_delta_2.is_zero() {
-        vault.deposit_fee = 0;
-    // Assuming 18 decimals, if the delta of 1e20 is half the delta of 2e20, relative fee.
-    // Delta / (amount without fee / 10000) to give us the fee in basis points
-    } else if deposit_fee_delta_1 * U256::from(2) == deposit_fee_delta_2 {
-        vault.deposit_fee = (deposit_fee_delta_1 / (deposit_no_fee / U256::from(10_000))).to();
-    } else {
-        // If not a relative fee or zero, ignore vault
-        return Err(AMMError::BatchRequestError(vault.address()));
-    }
+    ) = vault_data;
 
-    // If both deltas are zero, the fee is zero
-    if withdraw_fee_delta_1.is_zero() && withdraw_fee_delta_2.is_zero() {
-        vault.withdraw_fee = 0;
-    // Assuming 18 decimals, if the delta of 1e20 is half the delta of 2e20, relative fee.
-    // Delta / (amount without fee / 10000) to give us the fee in basis points
-    } else if withdraw_fee_delta_1 * U256::from(2) == withdraw_fee_delta_2 {
-        vault.withdraw_fee = (withdraw_fee_delta_1 / (withdraw_no_fee / U256::from(10_000))).to();
-    } else {
-        // If not a relative fee or zero, ignore vault
-        return Err(AMMError::BatchRequestError(vault.address()));
-    }
+    let deposit_fee = relative_fee(deposit_fee_delta_1, deposit_fee_delta_2, deposit_no_fee)?;
+    let withdraw_fee = relative_fee(withdraw_fee_delta_1, withdraw_fee_delta_2, withdraw_no_fee)?;
 
     // if above does not error => populate the vault
     vault.vault_token = vault_token;
@@ -93,6 +142,129 @@ where
     vault.asset_token_decimals = asset_token_dec as u8;
     vault.vault_reserve = vault_reserve;
     vault.asset_reserve = asset_reserve;
+    vault.deposit_fee = deposit_fee;
+    vault.withdraw_fee = withdraw_fee;
 
     Ok(())
 }
+
+/// Derives a fee in basis points from the fee deltas of quoting 1e20 and 2e20 tokens with
+/// [`derive_fee`].
+///
+/// Returns [`AMMError::InvalidERC4626Fee`] if the fee is neither zero nor relative.
+fn relative_fee(delta_1: U256, delta_2: U256, amount_no_fee: U256) -> Result<u32, AMMError> {
+    // If both deltas are zero, the fee is zero
+    if delta_1.is_zero() && delta_2.is_zero() {
+        return Ok(0);
+    }
+
+    // Assuming 18 decimals, if the delta of 1e20 is half the delta of 2e20, relative fee
+    if delta_1 * U256::from(2) != delta_2 || amount_no_fee.is_zero() {
+        return Err(AMMError::InvalidERC4626Fee);
+    }
+
+    // A delta larger than the amount is a fee of 100% or more, rejected by `derive_fee`
+    derive_fee(amount_no_fee.saturating_sub(delta_1), amount_no_fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::address;
+
+    #[test]
+    fn test_populate_vault_data() {
+        let vault_token = address!("83F20F44975D03b1b09e64809B757c47f942BEeA");
+        let asset_token = address!("6B175474E89094C44Da98b954EedeAC495271d0F");
+        let mut vault = ERC4626Vault {
+            vault_token,
+            ..Default::default()
+        };
+
+        // 100 and 200 tokens quoted without a deposit fee and with a 0.1% withdraw fee
+        let deposit_no_fee = U256::from(91_300_000_000_000_000_000_u128);
+        let withdraw_no_fee = U256::from(109_500_000_000_000_000_000_u128);
+        let withdraw_delta = withdraw_no_fee * U256::from(10) / U256::from(10_000);
+        let vault_data = (
+            vault_token,
+            18,
+            asset_token,
+            18,
+            U256::from(1_000_000_000_000_000_000_000_000_u128),
+            U256::from(1_095_000_000_000_000_000_000_000_u128),
+            U256::ZERO,
+            U256::ZERO,
+            deposit_no_fee,
+            withdraw_delta,
+            withdraw_delta * U256::from(2),
+            withdraw_no_fee,
+        );
+
+        populate_vault_data(&mut vault, vault_data).unwrap();
+        assert!(vault.data_is_populated());
+        assert_eq!(vault.vault_token, vault_token);
+        assert_eq!(vault.asset_token, asset_token);
+        assert_eq!(vault.vault_token_decimals, 18);
+        assert_eq!(vault.asset_token_decimals, 18);
+        assert_eq!(vault.deposit_fee, 0);
+        assert_eq!(vault.withdraw_fee, 10);
+    }
+
+    #[test]
+    fn test_skipped_vault_data() {
+        let address = Address::with_last_byte(1);
+        let mut vault = ERC4626Vault {
+            vault_token: address,
+            ..Default::default()
+        };
+
+        let vault_data = (
+            Address::ZERO,
+            0,
+            Address::ZERO,
+            0,
+            U256::ZERO,
+            U256::ZERO,
+            U256::ZERO,
+            U256::ZERO,
+            U256::ZERO,
+            U256::ZERO,
+            U256::ZERO,
+            U256::ZERO,
+        );
+        assert!(matches!(
+            populate_vault_data(&mut vault, vault_data),
+            Err(AMMError::BatchRequestError(vault_token)) if vault_token == address
+        ));
+        assert_eq!(vault.vault_token, address);
+        assert!(vault.asset_token.is_zero());
+    }
+
+    #[test]
+    fn test_relative_fee() {
+        let amount = U256::from(10).pow(U256::from(20));
+        let delta = amount * U256::from(30) / U256::from(10_000);
+
+        assert_eq!(relative_fee(U256::ZERO, U256::ZERO, amount).unwrap(), 0);
+        assert_eq!(
+            relative_fee(delta, delta * U256::from(2), amount).unwrap(),
+            30
+        );
+
+        // A flat fee does not double with the amount
+        assert!(matches!(
+            relative_fee(delta, delta, amount),
+            Err(AMMError::InvalidERC4626Fee)
+        ));
+        // No amount to relate the deltas to
+        assert!(matches!(
+            relative_fee(delta, delta * U256::from(2), U256::ZERO),
+            Err(AMMError::InvalidERC4626Fee)
+        ));
+        // A fee of 100% or more
+        assert!(matches!(
+            relative_fee(amount, amount * U256::from(2), amount),
+            Err(AMMError::InvalidERC4626Fee)
+        ));
+    }
+}
@@ -3,6 +3,7 @@ pub mod batch_request;
 use std::cmp::Ordering;
 
 use alloy::{
+    eips::BlockId,
     network::Network,
     primitives::{Address, B256, U256},
     providers::Provider,
@@ -28,13 +29,20 @@ sol! {
     contract IERC4626Vault {
         event Withdraw(address indexed sender, address indexed receiver, address indexed owner, uint256 assets, uint256 shares);
         event Deposit(address indexed sender,address indexed owner, uint256 assets, uint256 shares);
+        function asset() external view returns (address);
         function totalAssets() external view returns (uint256);
         function totalSupply() external view returns (uint256);
         function decimals() external view returns (uint8);
+        function convertToShares(uint256 assets) external view returns (uint256);
+        function convertToAssets(uint256 shares) external view returns (uint256);
+        function previewDeposit(uint256 assets) external view returns (uint256);
+        function previewRedeem(uint256 shares) external view returns (uint256);
+        function maxDeposit(address receiver) external view returns (uint256);
+        function maxWithdraw(address owner) external view returns (uint256);
     }
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ERC4626Vault {
     /// token received from depositing, i.e. shares token
     pub vault_token: Address,
@@ -50,6 +58,36 @@ pub struct ERC4626Vault {
     pub deposit_fee: u32,
     /// withdrawal fee in basis points
     pub withdraw_fee: u32,
+    /// maximum amount of asset tokens that can be deposited, `U256::MAX` if unlimited
+    #[serde(default = "unlimited")]
+    pub max_deposit: U256,
+    /// maximum amount of asset tokens that can be withdrawn, `U256::MAX` if unlimited
+    ///
+    /// Only synced by [`sync_limits`](Self::sync_limits) with an owner, as `maxWithdraw` depends
+    /// on the balance of its owner
+    #[serde(default = "unlimited")]
+    pub max_withdraw: U256,
+}
+
+fn unlimited() -> U256 {
+    U256::MAX
+}
+
+impl Default for ERC4626Vault {
+    fn default() -> Self {
+        ERC4626Vault {
+            vault_token: Address::ZERO,
+            vault_token_decimals: 0,
+            asset_token: Address::ZERO,
+            asset_token_decimals: 0,
+            vault_reserve: U256::ZERO,
+            asset_reserve: U256::ZERO,
+            deposit_fee: 0,
+            withdraw_fee: 0,
+            max_deposit: U256::MAX,
+            max_withdraw: U256::MAX,
+        }
+    }
 }
 
 #[async_trait]
@@ -77,13 +115,7 @@ impl AutomatedMarketMaker for ERC4626Vault {
         N: Network,
         P: Provider<N> + Clone,
     {
-        let (vault_reserve, asset_reserve) = self.get_reserves(provider).await?;
-        tracing::debug!(vault_reserve = ?vault_reserve, asset_reserve = ?asset_reserve, address = ?self.vault_token, "ER4626 sync");
-
-        self.vault_reserve = vault_reserve;
-        self.asset_reserve = asset_reserve;
-
-        Ok(())
+        self.sync_rate(None, provider).await
     }
 
     fn sync_on_event_signatures(&self) -> Vec<B256> {
@@ -110,16 +142,14 @@ impl AutomatedMarketMaker for ERC4626Vault {
     #[instrument(skip(self, provider), level = "debug")]
     async fn populate_data<N, P>(
         &mut self,
-        _block_number: Option<u64>,
+        block_number: Option<u64>,
         provider: P,
     ) -> Result<(), AMMError>
     where
         N: Network,
         P: Provider<N> + Clone,
     {
-        batch_request::get_4626_vault_data_batch_request(self, provider.clone()).await?;
-
-        Ok(())
+        batch_request::get_4626_vault_data_batch_request(self, block_number, provider).await
     }
 
     fn simulate_swap(
@@ -129,8 +159,13 @@ impl AutomatedMarketMaker for ERC4626Vault {
         amount_in: U256,
     ) -> Result<U256, AMMError> {
         if self.vault_token == base_token {
-            Ok(self.get_amount_out(amount_in, self.vault_reserve, self.asset_reserve))
+            let amount_out = self.get_amount_out(amount_in, self.vault_reserve, self.asset_reserve);
+            check_limit(amount_out, self.max_withdraw)?;
+
+            Ok(amount_out)
         } else {
+            check_limit(amount_in, self.max_deposit)?;
+
             Ok(self.get_amount_out(amount_in, self.asset_reserve, self.vault_reserve))
         }
     }
@@ -143,16 +178,24 @@ impl AutomatedMarketMaker for ERC4626Vault {
     ) -> Result<U256, AMMError> {
         if self.vault_token == base_token {
             let amount_out = self.get_amount_out(amount_in, self.vault_reserve, self.asset_reserve);
+            check_limit(amount_out, self.max_withdraw)?;
 
             self.vault_reserve -= amount_in;
             self.asset_reserve -= amount_out;
+            if self.max_withdraw != U256::MAX {
+                self.max_withdraw -= amount_out;
+            }
 
             Ok(amount_out)
         } else {
+            check_limit(amount_in, self.max_deposit)?;
             let amount_out = self.get_amount_out(amount_in, self.asset_reserve, self.vault_reserve);
 
             self.asset_reserve += amount_in;
             self.vault_reserve += amount_out;
+            if self.max_deposit != U256::MAX {
+                self.max_deposit -= amount_in;
+            }
 
             Ok(amount_out)
         }
@@ -180,6 +223,8 @@ impl ERC4626Vault {
             asset_reserve,
             deposit_fee,
             withdraw_fee,
+            max_deposit: U256::MAX,
+            max_withdraw: U256::MAX,
         }
     }
 
@@ -190,13 +235,7 @@ impl ERC4626Vault {
     {
         let mut vault = ERC4626Vault {
             vault_token,
-            vault_token_decimals: 0,
-            asset_token: Address::ZERO,
-            asset_token_decimals: 0,
-            vault_reserve: U256::ZERO,
-            asset_reserve: U256::ZERO,
-            deposit_fee: 0,
-            withdraw_fee: 0,
+            ..Default::default()
         };
 
         vault.populate_data(None, provider.clone()).await?;
@@ -240,6 +279,75 @@ impl ERC4626Vault {
         Ok((total_supply, total_assets))
     }
 
+    /// Syncs the reserves to the current share rate.
+    ///
+    /// The asset reserve is set to `convertToAssets(totalSupply)`, so yield accrual, harvests and
+    /// losses that emit no `Deposit` or `Withdraw` log are reflected in the price.
+    pub async fn sync_rate<N, P>(
+        &mut self,
+        block_number: Option<u64>,
+        provider: P,
+    ) -> Result<(), AMMError>
+    where
+        N: Network,
+        P: Provider<N> + Clone,
+    {
+        let block = block_number.map_or(BlockId::latest(), BlockId::from);
+        let vault = IERC4626Vault::new(self.vault_token, provider);
+
+        let IERC4626Vault::totalSupplyReturn { _0: total_supply } =
+            vault.totalSupply().block(block).call().await?;
+
+        let asset_reserve = if total_supply.is_zero() {
+            vault.totalAssets().block(block).call().await?._0
+        } else {
+            vault
+                .convertToAssets(total_supply)
+                .block(block)
+                .call()
+                .await?
+                ._0
+        };
+
+        tracing::debug!(vault_reserve = ?total_supply, ?asset_reserve, address = ?self.vault_token, "ERC4626 rate sync");
+
+        self.vault_reserve = total_supply;
+        self.asset_reserve = asset_reserve;
+
+        Ok(())
+    }
+
+    /// Syncs the deposit limit and, if `owner` is set, the withdraw limit of `owner`.
+    ///
+    /// `maxWithdraw` depends on the balance of its owner, so the withdraw limit is left unchanged
+    /// when no owner is given.
+    pub async fn sync_limits<N, P>(
+        &mut self,
+        owner: Option<Address>,
+        block_number: Option<u64>,
+        provider: P,
+    ) -> Result<(), AMMError>
+    where
+        N: Network,
+        P: Provider<N> + Clone,
+    {
+        let block = block_number.map_or(BlockId::latest(), BlockId::from);
+        let vault = IERC4626Vault::new(self.vault_token, provider);
+
+        self.max_deposit = vault
+            .maxDeposit(owner.unwrap_or_default())
+            .block(block)
+            .call()
+            .await?
+            ._0;
+
+        if let Some(owner) = owner {
+            self.max_withdraw = vault.maxWithdraw(owner).block(block).call().await?._0;
+        }
+
+        Ok(())
+    }
+
     pub fn calculate_price_64_x_64(&self, base_token: Address) -> Result<u128, ArithmeticError> {
         let decimal_shift = self.vault_token_decimals as i8 - self.asset_token_decimals as i8;
 
@@ -290,6 +398,16 @@ impl ERC4626Vault {
         amount_in * reserve_out / reserve_in * U256::from(10000 - fee) / U256::from(10000)
     }
 
+    /// Returns the share rate as the amount of asset tokens received for one vault token.
+    pub fn share_price(&self) -> U256 {
+        let one_share = U256::from(10).pow(U256::from(self.vault_token_decimals));
+        if self.vault_reserve.is_zero() {
+            return one_share;
+        }
+
+        one_share * self.asset_reserve / self.vault_reserve
+    }
+
     pub fn sync_from_deposit_log(
         &mut self,
         log: Log,
@@ -319,6 +437,38 @@ impl ERC4626Vault {
     }
 }
 
+/// Derives a fee in basis points from an amount quoted with fees and the same amount quoted
+/// without fees.
+///
+/// Returns [`AMMError::InvalidERC4626Fee`] if the amount with fees is larger than the amount
+/// without, or if the fee is 100% or more.
+pub fn derive_fee(amount_with_fee: U256, amount_no_fee: U256) -> Result<u32, AMMError> {
+    if amount_with_fee > amount_no_fee {
+        return Err(AMMError::InvalidERC4626Fee);
+    }
+
+    // Nothing is quoted either way, e.g. for an empty vault
+    if amount_no_fee.is_zero() {
+        return Ok(0);
+    }
+
+    let fee = (amount_no_fee - amount_with_fee) * U256::from(10_000) / amount_no_fee;
+    if fee >= U256::from(10_000) {
+        return Err(AMMError::InvalidERC4626Fee);
+    }
+
+    Ok(fee.to())
+}
+
+/// Returns an error if `amount` exceeds a vault limit.
+fn check_limit(amount: U256, limit: U256) -> Result<(), AMMError> {
+    if amount > limit {
+        return Err(AMMError::ERC4626LimitExceeded { amount, limit });
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
@@ -328,9 +478,9 @@ mod tests {
         providers::ProviderBuilder,
     };
 
-    use crate::amm::AutomatedMarketMaker;
+    use crate::{amm::AutomatedMarketMaker, errors::AMMError};
 
-    use super::ERC4626Vault;
+    use super::{derive_fee, ERC4626Vault};
 
     #[tokio::test]
     async fn test_get_vault_data() {
@@ -354,6 +504,30 @@ mod tests {
         assert_eq!(vault.withdraw_fee, 0);
     }
 
+    #[test]
+    fn test_derive_fee() {
+        let amount = U256::from(10).pow(U256::from(22));
+
+        assert_eq!(derive_fee(amount, amount).unwrap(), 0);
+        // Rounding in the vault is below a basis point
+        assert_eq!(derive_fee(amount - U256::from(1), amount).unwrap(), 0);
+        assert_eq!(
+            derive_fee(amount * U256::from(9_970) / U256::from(10_000), amount).unwrap(),
+            30
+        );
+        assert_eq!(derive_fee(U256::ZERO, U256::ZERO).unwrap(), 0);
+
+        // A preview quoting more than its conversion or taking everything is inconsistent
+        assert!(matches!(
+            derive_fee(amount + U256::from(1), amount),
+            Err(AMMError::InvalidERC4626Fee)
+        ));
+        assert!(matches!(
+            derive_fee(U256::ZERO, amount),
+            Err(AMMError::InvalidERC4626Fee)
+        ));
+    }
+
     #[tokio::test]
     async fn test_calculate_price_varying_decimals() {
         let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT").unwrap();
@@ -455,6 +629,57 @@ mod tests {
         assert_eq!(price_a_64_x, 18318109959350028841);
     }
 
+    #[test]
+    fn test_simulate_swap_limits() {
+        let mut vault = ERC4626Vault {
+            vault_token: address!("163538E22F4d38c1eb21B79939f3d2ee274198Ff"),
+            asset_token: address!("6B175474E89094C44Da98b954EedeAC495271d0F"),
+            vault_reserve: U256::from(1_000_000),
+            asset_reserve: U256::from(2_000_000),
+            max_deposit: U256::from(1_000),
+            ..Default::default()
+        };
+
+        assert!(vault
+            .simulate_swap(vault.asset_token, vault.vault_token, U256::from(1_001))
+            .is_err());
+
+        // Deposits count against the deposit limit
+        let shares_out = vault
+            .simulate_swap_mut(vault.asset_token, vault.vault_token, U256::from(600))
+            .unwrap();
+        assert_eq!(shares_out, U256::from(300));
+        assert_eq!(vault.max_deposit, U256::from(400));
+        assert!(vault
+            .simulate_swap_mut(vault.asset_token, vault.vault_token, U256::from(600))
+            .is_err());
+
+        // Withdrawals are unlimited unless synced for an owner
+        vault.max_withdraw = U256::from(100);
+        assert!(vault
+            .simulate_swap(vault.vault_token, vault.asset_token, U256::from(100))
+            .is_err());
+        assert_eq!(
+            vault
+                .simulate_swap(vault.vault_token, vault.asset_token, U256::from(50))
+                .unwrap(),
+            U256::from(100)
+        );
+    }
+
+    #[test]
+    fn test_share_price() {
+        let vault = ERC4626Vault {
+            vault_token_decimals: 6,
+            vault_reserve: U256::from(1_000_000),
+            asset_reserve: U256::from(1_050_000),
+            ..Default::default()
+        };
+        assert_eq!(vault.share_price(), U256::from(1_050_000));
+
+        assert_eq!(ERC4626Vault::default().share_price(), U256::from(1));
+    }
+
     #[tokio::test]
     async fn test_simulate_swap() {
         let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT").unwrap();
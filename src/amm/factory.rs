@@ -8,10 +8,12 @@ use alloy::{
     sol_types::SolEvent,
 };
 use async_trait::async_trait;
-use futures::stream::{FuturesUnordered, StreamExt};
 use serde::{Deserialize, Serialize};
 
-use crate::errors::{AMMError, EventLogError};
+use crate::{
+    errors::{AMMError, EventLogError},
+    rpc::get_logs::{get_logs_in_chunks, LogRangeConfig},
+};
 
 use super::{
     balancer_v2::factory::{BalancerV2Factory, IBFactory},
@@ -168,9 +170,30 @@ macro_rules! factory {
 factory!(UniswapV2Factory, UniswapV3Factory, BalancerV2Factory);
 
 impl Factory {
+    /// Computes the address the factory deploys `amm` at from its tokens, without querying the
+    /// factory.
+    ///
+    /// An AMM created by the factory is genuine if its address matches. Returns `None` if the
+    /// factory does not deploy `amm`'s type at deterministic addresses or its init code hash is
+    /// unknown.
+    pub fn compute_amm_address(&self, amm: &AMM) -> Option<Address> {
+        match (self, amm) {
+            (Factory::UniswapV2Factory(factory), AMM::UniswapV2Pool(pool)) => {
+                factory.pair_address(pool.token_a, pool.token_b)
+            }
+            (Factory::UniswapV3Factory(factory), AMM::UniswapV3Pool(pool)) => {
+                factory.pool_address(pool.token_a, pool.token_b, pool.fee)
+            }
+            _ => None,
+        }
+    }
+
+    /// Gets all AMMs created by the factory from `from_block` to `to_block` (inclusive).
+    ///
+    /// `step` is the initial block range for each `eth_getLogs` request and adapts to provider limits.
     pub async fn get_all_pools_from_logs<N, P>(
         &self,
-        mut from_block: u64,
+        from_block: u64,
         to_block: u64,
         step: u64,
         provider: P,
@@ -179,36 +202,22 @@ impl Factory {
         N: Network,
         P: Provider<N> + Clone,
     {
-        let factory_address = self.address();
-        let amm_created_event_signature = self.amm_created_event_signature();
-        let mut futures = FuturesUnordered::new();
+        let filter = Filter::new()
+            .event_signature(self.amm_created_event_signature())
+            .address(self.address());
+
+        let logs = get_logs_in_chunks(
+            &filter,
+            from_block,
+            to_block,
+            LogRangeConfig::new(step),
+            provider,
+        )
+        .await?;
 
         let mut aggregated_amms: Vec<AMM> = vec![];
-
-        while from_block < to_block {
-            let provider = provider.clone();
-            let mut target_block = from_block + step - 1;
-            if target_block > to_block {
-                target_block = to_block;
-            }
-
-            let filter = Filter::new()
-                .event_signature(amm_created_event_signature)
-                .address(factory_address)
-                .from_block(from_block)
-                .to_block(target_block);
-
-            futures.push(async move { provider.get_logs(&filter).await });
-
-            from_block += step;
-        }
-
-        while let Some(result) = futures.next().await {
-            let logs = result.map_err(AMMError::TransportError)?;
-
-            for log in logs {
-                aggregated_amms.push(self.new_empty_amm_from_log(log)?);
-            }
+        for log in logs {
+            aggregated_amms.push(self.new_empty_amm_from_log(log)?);
         }
 
         Ok(aggregated_amms)
@@ -15,7 +15,7 @@ use alloy::{
     sol,
 };
 use async_trait::async_trait;
-use balancer_v2::BalancerV2Pool;
+use balancer_v2::{vault::BalancerV2VaultPool, BalancerV2Pool};
 use serde::{Deserialize, Serialize};
 
 use crate::errors::AMMError;
@@ -29,6 +29,9 @@ sol! {
     contract IErc20 {
         function balanceOf(address account) external view returns (uint256);
         function decimals() external view returns (uint8);
+        function symbol() external view returns (string);
+        function name() external view returns (string);
+        function totalSupply() external view returns (uint256);
     }
 }
 
@@ -183,4 +186,10 @@ macro_rules! amm {
     };
 }
 
-amm!(UniswapV2Pool, UniswapV3Pool, ERC4626Vault, BalancerV2Pool);
+amm!(
+    UniswapV2Pool,
+    UniswapV3Pool,
+    ERC4626Vault,
+    BalancerV2Pool,
+    BalancerV2VaultPool
+);
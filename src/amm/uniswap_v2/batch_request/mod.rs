@@ -42,7 +42,11 @@ where
     Ok(<Vec<Address> as SolValue>::abi_decode(&res, false)?)
 }
 
-pub async fn get_amm_data_batch_request<N, P>(amms: &mut [AMM], provider: P) -> Result<(), AMMError>
+pub async fn get_amm_data_batch_request<N, P>(
+    amms: &mut [AMM],
+    block_number: Option<u64>,
+    provider: P,
+) -> Result<(), AMMError>
 where
     N: Network,
     P: Provider<N> + Clone,
@@ -53,7 +57,11 @@ where
     }
 
     let deployer = IGetUniswapV2PoolDataBatchRequest::deploy_builder(provider, target_addresses);
-    let res = deployer.call().await?;
+    let res = if let Some(block_number) = block_number {
+        deployer.block(block_number.into()).call_raw().await?
+    } else {
+        deployer.call_raw().await?
+    };
 
     let pools =
         <Vec<(Address, u16, Address, u16, u128, u128)> as SolValue>::abi_decode(&res, false)?;
@@ -84,6 +92,7 @@ where
 
 pub async fn get_v2_pool_data_batch_request<N, P>(
     pool: &mut UniswapV2Pool,
+    block_number: Option<u64>,
     provider: P,
 ) -> Result<(), AMMError>
 where
@@ -91,7 +100,11 @@ where
     P: Provider<N> + Clone,
 {
     let deployer = IGetUniswapV2PoolDataBatchRequest::deploy_builder(provider, vec![pool.address]);
-    let res = deployer.call_raw().await?;
+    let res = if let Some(block_number) = block_number {
+        deployer.block(block_number.into()).call_raw().await?
+    } else {
+        deployer.call_raw().await?
+    };
 
     let data =
         <Vec<(Address, u16, Address, u16, u128, u128)> as SolValue>::abi_decode(&res, false)?;
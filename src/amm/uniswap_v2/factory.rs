@@ -1,6 +1,6 @@
 use alloy::{
     network::Network,
-    primitives::{Address, B256, U256},
+    primitives::{b256, keccak256, Address, B256, U256},
     providers::Provider,
     rpc::types::eth::Log,
     sol,
@@ -11,11 +11,12 @@ use async_trait::async_trait;
 use crate::{
     amm::{factory::AutomatedMarketMakerFactory, AMM},
     errors::AMMError,
+    sync::events::{self, SyncEvent},
 };
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-use super::{batch_request, UniswapV2Pool, U256_1};
+use super::{batch_request, IUniswapV2Pair, UniswapV2Pool, U256_1};
 
 sol! {
     /// Interface of the UniswapV2Factory contract
@@ -29,11 +30,60 @@ sol! {
     }
 }
 
+sol! {
+    /// Interface of fork pairs quoting their own swaps, e.g. pairs with a configurable fee
+    #[sol(rpc)]
+    contract IUniswapV2PairWithFee {
+        function getAmountOut(uint256 amountIn, address tokenIn) external view returns (uint256);
+    }
+}
+
+sol! {
+    /// Getters of the pair init code hash exposed by some fork factories
+    #[sol(rpc)]
+    contract IUniswapV2FactoryInitCodeHash {
+        function INIT_CODE_PAIR_HASH() external view returns (bytes32);
+        function pairCodeHash() external view returns (bytes32);
+    }
+}
+
+/// Fee of Uniswap V2 and most of its forks, 0.3%
+pub const DEFAULT_UNISWAP_V2_FEE: u32 = 300;
+
+/// A Uniswap V2 fork identified by the init code hash of its pairs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownUniswapV2Fork {
+    pub name: &'static str,
+    pub init_code_hash: B256,
+    pub fee: u32,
+}
+
+pub const KNOWN_UNISWAP_V2_FORKS: [KnownUniswapV2Fork; 3] = [
+    KnownUniswapV2Fork {
+        name: "Uniswap V2",
+        init_code_hash: b256!("96e8ac4277198ff8b6f785478aa9a39f403cb768dd02cbee326c3e7da348845f"),
+        fee: 300,
+    },
+    KnownUniswapV2Fork {
+        name: "SushiSwap",
+        init_code_hash: b256!("e18a34eb0e04b04f7a0ac29a6e80748dca96319b42c520bfc7c1d9e3f12c4c8f"),
+        fee: 300,
+    },
+    KnownUniswapV2Fork {
+        name: "PancakeSwap V2",
+        init_code_hash: b256!("00fb7f630766e6a796048ea87d01acd3068e8ff67d078148a3fa3f4a84f69bd5"),
+        fee: 250,
+    },
+];
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct UniswapV2Factory {
     pub address: Address,
     pub creation_block: u64,
     pub fee: u32,
+    /// Hash of the pair init code, used to compute pair addresses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_code_hash: Option<B256>,
 }
 
 impl UniswapV2Factory {
@@ -42,9 +92,81 @@ impl UniswapV2Factory {
             address,
             creation_block,
             fee,
+            init_code_hash: None,
         }
     }
 
+    pub fn with_init_code_hash(mut self, init_code_hash: B256) -> Self {
+        self.init_code_hash = Some(init_code_hash);
+        self
+    }
+
+    /// Computes the address of the pair of `token_a` and `token_b` without querying the factory.
+    ///
+    /// Returns `None` if the init code hash of the factory is unknown.
+    pub fn pair_address(&self, token_a: Address, token_b: Address) -> Option<Address> {
+        let init_code_hash = self.init_code_hash?;
+        Some(compute_pair_address(
+            self.address,
+            init_code_hash,
+            token_a,
+            token_b,
+        ))
+    }
+
+    /// Detects the swap fee and pair init code hash of the factory from its first pair.
+    ///
+    /// The pair address is matched against the CREATE2 address of each of the
+    /// [`KNOWN_UNISWAP_V2_FORKS`], taking the fee of the matching fork. The fee of an unknown fork
+    /// is derived from the pair's own `getAmountOut` if it has one, and otherwise defaults to
+    /// [`DEFAULT_UNISWAP_V2_FEE`].
+    ///
+    /// The init code hash of an unknown fork is read from the `INIT_CODE_PAIR_HASH()` or
+    /// `pairCodeHash()` getter of the factory, and only kept if it computes the address of the
+    /// first pair. It is left unset otherwise.
+    pub async fn detect_fork<N, P>(&mut self, provider: P) -> Result<(), AMMError>
+    where
+        N: Network,
+        P: Provider<N> + Clone,
+    {
+        let factory = IUniswapV2Factory::new(self.address, provider.clone());
+        let IUniswapV2Factory::allPairsReturn { pair } =
+            factory.allPairs(U256::ZERO).call().await?;
+
+        let v2_pair = IUniswapV2Pair::new(pair, provider.clone());
+        let token_0 = v2_pair.token0().call().await?._0;
+        let token_1 = v2_pair.token1().call().await?._0;
+
+        if let Some(fork) = KNOWN_UNISWAP_V2_FORKS.iter().find(|fork| {
+            compute_pair_address(self.address, fork.init_code_hash, token_0, token_1) == pair
+        }) {
+            tracing::debug!(factory = ?self.address, fork = fork.name, "detected known Uniswap V2 fork");
+            self.init_code_hash = Some(fork.init_code_hash);
+            self.fee = fork.fee;
+            return Ok(());
+        }
+
+        self.init_code_hash = probe_init_code_hash(self.address, provider.clone())
+            .await
+            .into_iter()
+            .find(|init_code_hash| {
+                compute_pair_address(self.address, *init_code_hash, token_0, token_1) == pair
+            });
+        if self.init_code_hash.is_none() {
+            tracing::debug!(factory = ?self.address, "could not derive init code hash of unknown Uniswap V2 fork");
+        }
+
+        self.fee = match probe_pair_fee(pair, token_0, provider).await {
+            Some(fee) => fee,
+            None => {
+                tracing::debug!(factory = ?self.address, "could not detect fee of unknown Uniswap V2 fork, using default");
+                DEFAULT_UNISWAP_V2_FEE
+            }
+        };
+
+        Ok(())
+    }
+
     pub async fn get_all_pairs_via_batched_calls<N, P>(
         &self,
         provider: P,
@@ -80,6 +202,12 @@ impl UniswapV2Factory {
                 .await?,
             );
 
+            events::emit(SyncEvent::PairIndicesSynced {
+                factory: self.address,
+                synced: pairs.len(),
+                total: pairs_length.to::<usize>(),
+            });
+
             idx_from = idx_to;
 
             if idx_to + U256::from(step) > pairs_length {
@@ -105,6 +233,89 @@ impl UniswapV2Factory {
     }
 }
 
+/// Computes the CREATE2 address of the pair of `token_a` and `token_b` deployed by `factory`.
+pub fn compute_pair_address(
+    factory: Address,
+    init_code_hash: B256,
+    token_a: Address,
+    token_b: Address,
+) -> Address {
+    let (token_0, token_1) = if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    };
+
+    let salt = keccak256([token_0.as_slice(), token_1.as_slice()].concat());
+    factory.create2(salt, init_code_hash)
+}
+
+/// Returns the init code hashes exposed by the getters of `factory`, unverified.
+async fn probe_init_code_hash<N, P>(factory: Address, provider: P) -> Vec<B256>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let factory = IUniswapV2FactoryInitCodeHash::new(factory, provider);
+
+    let mut init_code_hashes = vec![];
+    if let Ok(init_code_hash) = factory.INIT_CODE_PAIR_HASH().call().await {
+        init_code_hashes.push(init_code_hash._0);
+    }
+    if let Ok(init_code_hash) = factory.pairCodeHash().call().await {
+        init_code_hashes.push(init_code_hash._0);
+    }
+
+    init_code_hashes
+}
+
+/// Derives the fee of `pair` by quoting a swap of 0.1% of the `token_in` reserve with the pair's
+/// `getAmountOut`.
+///
+/// Returns `None` if the pair has no such function or no reserves.
+async fn probe_pair_fee<N, P>(pair: Address, token_in: Address, provider: P) -> Option<u32>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let pool = UniswapV2Pool {
+        address: pair,
+        ..Default::default()
+    };
+    let (reserve_in, reserve_out) = pool.get_reserves(provider.clone()).await.ok()?;
+    let (reserve_in, reserve_out) = (U256::from(reserve_in), U256::from(reserve_out));
+
+    let amount_in = reserve_in / U256::from(1000);
+    let amount_out = IUniswapV2PairWithFee::new(pair, provider)
+        .getAmountOut(amount_in, token_in)
+        .call()
+        .await
+        .ok()?
+        ._0;
+
+    derive_fee(amount_in, amount_out, reserve_in, reserve_out)
+}
+
+/// Derives the fee, in the units of [`UniswapV2Pool::fee`], from a constant product quote,
+/// rounded to the nearest basis point.
+fn derive_fee(
+    amount_in: U256,
+    amount_out: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+) -> Option<u32> {
+    if amount_in.is_zero() || amount_out.is_zero() || amount_out >= reserve_out {
+        return None;
+    }
+
+    // amount_out = amount_in * (1 - fee) * reserve_out / (reserve_in + amount_in * (1 - fee))
+    let fee_complement =
+        amount_out * reserve_in * U256::from(100_000) / (amount_in * (reserve_out - amount_out));
+    let fee = U256::from(100_000).checked_sub(fee_complement)?;
+
+    Some(((fee + U256::from(5)) / U256::from(10) * U256::from(10)).to())
+}
+
 #[async_trait]
 impl AutomatedMarketMakerFactory for UniswapV2Factory {
     fn address(&self) -> Address {
@@ -165,7 +376,7 @@ impl AutomatedMarketMakerFactory for UniswapV2Factory {
     async fn populate_amm_data<N, P>(
         &self,
         amms: &mut [AMM],
-        _block_number: Option<u64>,
+        block_number: Option<u64>,
         provider: P,
     ) -> Result<(), AMMError>
     where
@@ -175,7 +386,12 @@ impl AutomatedMarketMakerFactory for UniswapV2Factory {
         // Max batch size for call
         let step = 127;
         for amm_chunk in amms.chunks_mut(step) {
-            batch_request::get_amm_data_batch_request(amm_chunk, provider.clone()).await?;
+            batch_request::get_amm_data_batch_request(amm_chunk, block_number, provider.clone())
+                .await?;
+
+            events::emit(SyncEvent::PopulateChunkSynced {
+                amms: amm_chunk.len(),
+            });
         }
         Ok(())
     }
@@ -184,3 +400,62 @@ impl AutomatedMarketMakerFactory for UniswapV2Factory {
         self.creation_block
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::address;
+
+    #[test]
+    fn test_compute_pair_address() {
+        let usdc = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+        let weth = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+
+        let pair = compute_pair_address(
+            address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
+            KNOWN_UNISWAP_V2_FORKS[0].init_code_hash,
+            weth,
+            usdc,
+        );
+        assert_eq!(pair, address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc"));
+
+        let factory = UniswapV2Factory::new(
+            address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
+            10000835,
+            300,
+        );
+        assert_eq!(factory.pair_address(usdc, weth), None);
+
+        let factory = factory.with_init_code_hash(KNOWN_UNISWAP_V2_FORKS[0].init_code_hash);
+        assert_eq!(factory.pair_address(usdc, weth), Some(pair));
+    }
+
+    #[test]
+    fn test_derive_fee() {
+        let reserve = U256::from(10).pow(U256::from(24));
+        let amount_in = reserve / U256::from(1000);
+
+        // Uniswap V2 quote
+        let pool = UniswapV2Pool {
+            fee: 300,
+            ..Default::default()
+        };
+        let amount_out = pool.get_amount_out(amount_in, reserve, reserve);
+        assert_eq!(
+            derive_fee(amount_in, amount_out, reserve, reserve),
+            Some(300)
+        );
+
+        // PancakeSwap V2 quote, 0.25% fee
+        let amount_in_with_fee = amount_in * U256::from(9975);
+        let amount_out =
+            amount_in_with_fee * reserve / (reserve * U256::from(10_000) + amount_in_with_fee);
+        assert_eq!(
+            derive_fee(amount_in, amount_out, reserve, reserve),
+            Some(250)
+        );
+
+        assert_eq!(derive_fee(amount_in, reserve, reserve, reserve), None);
+        assert_eq!(derive_fee(U256::ZERO, amount_out, reserve, reserve), None);
+    }
+}
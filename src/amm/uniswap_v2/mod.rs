@@ -33,6 +33,9 @@ sol! {
     }
 }
 
+/// Denominator of [`UniswapV2Pool::fee`], a fee of 300 is 0.3%
+pub const FEE_DENOMINATOR: u32 = 100_000;
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UniswapV2Pool {
     pub address: Address,
@@ -71,14 +74,14 @@ impl AutomatedMarketMaker for UniswapV2Pool {
     #[instrument(skip(self, provider), level = "debug")]
     async fn populate_data<N, P>(
         &mut self,
-        _block_number: Option<u64>,
+        block_number: Option<u64>,
         provider: P,
     ) -> Result<(), AMMError>
     where
         N: Network,
         P: Provider<N> + Clone,
     {
-        batch_request::get_v2_pool_data_batch_request(self, provider.clone()).await?;
+        batch_request::get_v2_pool_data_batch_request(self, block_number, provider.clone()).await?;
 
         Ok(())
     }
@@ -454,16 +457,35 @@ impl UniswapV2Pool {
         if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
             return U256::ZERO;
         }
-        let fee = (10000 - (self.fee / 10)) / 10; //Fee of 300 => (10,000 - 30) / 10  = 997
+        // Fee of 300 => 100,000 - 300 = 99,700
+        let fee = FEE_DENOMINATOR - self.fee;
         let amount_in_with_fee = amount_in * U256::from(fee);
         let numerator = amount_in_with_fee * reserve_out;
-        let denominator = reserve_in * U256::from(1000) + amount_in_with_fee;
+        let denominator = reserve_in * U256::from(FEE_DENOMINATOR) + amount_in_with_fee;
 
         tracing::trace!(?fee, ?amount_in_with_fee, ?numerator, ?denominator);
 
         numerator / denominator
     }
 
+    /// Calculates the amount in required to receive `amount_out`, mirroring `UniswapV2Library.getAmountIn`.
+    ///
+    /// Returns zero if the reserves cannot cover `amount_out`.
+    pub fn get_amount_in(&self, amount_out: U256, reserve_in: U256, reserve_out: U256) -> U256 {
+        tracing::trace!(?amount_out, ?reserve_in, ?reserve_out);
+
+        if amount_out.is_zero() || reserve_in.is_zero() || amount_out >= reserve_out {
+            return U256::ZERO;
+        }
+        let fee = FEE_DENOMINATOR - self.fee;
+        let numerator = reserve_in * amount_out * U256::from(FEE_DENOMINATOR);
+        let denominator = (reserve_out - amount_out) * U256::from(fee);
+
+        tracing::trace!(?fee, ?numerator, ?denominator);
+
+        numerator / denominator + U256::from(1)
+    }
+
     /// Returns the calldata for a swap.
     pub fn swap_calldata(
         &self,
@@ -596,6 +618,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_amount_in() {
+        let pool = UniswapV2Pool {
+            fee: 300,
+            ..Default::default()
+        };
+        let reserve_in = U256::from(1_000_000_000_u128);
+        let reserve_out = U256::from(2_000_000_000_u128);
+
+        let amount_out = U256::from(123_456_u128);
+        let amount_in = pool.get_amount_in(amount_out, reserve_in, reserve_out);
+
+        // The amount in is the smallest amount receiving at least `amount_out`
+        assert!(pool.get_amount_out(amount_in, reserve_in, reserve_out) >= amount_out);
+        assert!(
+            pool.get_amount_out(amount_in - U256::from(1), reserve_in, reserve_out) < amount_out
+        );
+
+        assert_eq!(
+            pool.get_amount_in(reserve_out, reserve_in, reserve_out),
+            U256::ZERO
+        );
+    }
+
+    #[test]
+    fn test_get_amount_out_with_fork_fee() {
+        let pool = UniswapV2Pool {
+            fee: 250,
+            ..Default::default()
+        };
+        let reserve_in = U256::from(1_000_000_000_000_u128);
+        let reserve_out = U256::from(2_000_000_000_000_u128);
+        let amount_in = U256::from(1_000_000_000_u128);
+
+        // PancakeSwap V2 quotes with a 0.25% fee out of 10,000
+        let amount_in_with_fee = amount_in * U256::from(9_975);
+        let expected = amount_in_with_fee * reserve_out
+            / (reserve_in * U256::from(10_000) + amount_in_with_fee);
+        assert_eq!(
+            pool.get_amount_out(amount_in, reserve_in, reserve_out),
+            expected
+        );
+
+        // A 0.3% fee quotes less
+        let default_fee_pool = UniswapV2Pool {
+            fee: 300,
+            ..Default::default()
+        };
+        assert!(default_fee_pool.get_amount_out(amount_in, reserve_in, reserve_out) < expected);
+
+        let amount_out = pool.get_amount_out(amount_in, reserve_in, reserve_out);
+        let amount_in_needed = pool.get_amount_in(amount_out, reserve_in, reserve_out);
+        assert!(amount_in_needed <= amount_in);
+        assert!(pool.get_amount_out(amount_in_needed, reserve_in, reserve_out) >= amount_out);
+    }
+
     #[tokio::test]
     async fn test_get_new_from_address() {
         let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT").unwrap();
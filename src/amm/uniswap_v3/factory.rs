@@ -2,20 +2,21 @@ use std::collections::{BTreeMap, HashMap};
 
 use alloy::{
     network::Network,
-    primitives::{Address, B256, U256},
+    primitives::{b256, Address, B256, U256},
     providers::Provider,
     rpc::types::eth::{Filter, Log},
     sol,
     sol_types::SolEvent,
 };
 use async_trait::async_trait;
-use futures::{stream::FuturesOrdered, StreamExt};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
 use crate::{
     amm::{factory::AutomatedMarketMakerFactory, AutomatedMarketMaker, AMM},
     errors::{AMMError, EventLogError},
+    rpc::get_logs::{get_logs_in_chunks, LogRangeConfig},
+    sync::events::{self, SyncEvent},
 };
 
 use super::{batch_request, compute_pool_key_hash, IUniswapV3Pool, UniswapV3Pool};
@@ -32,10 +33,20 @@ sol! {
     }
 }
 
+/// Fee tiers enabled on the Uniswap V3 factory, in hundredths of a basis point
+pub const UNISWAP_V3_FEE_TIERS: [u32; 4] = [100, 500, 3000, 10000];
+
+/// Hash of the Uniswap V3 pool init code
+pub const UNISWAP_V3_POOL_INIT_CODE_HASH: B256 =
+    b256!("e34f199b19b2b4f47f68442619d555527d244f78a3297ea89325f843f87b8b54");
+
 #[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct UniswapV3Factory {
     pub address: Address,
     pub creation_block: u64,
+    /// Hash of the pool init code, used to compute pool addresses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_code_hash: Option<B256>,
 }
 
 #[async_trait]
@@ -111,6 +122,10 @@ impl AutomatedMarketMakerFactory for UniswapV3Factory {
                     provider.clone(),
                 )
                 .await?;
+
+                events::emit(SyncEvent::PopulateChunkSynced {
+                    amms: amm_chunk.len(),
+                });
             }
         } else {
             return Err(AMMError::BlockNumberNotFound);
@@ -150,9 +165,30 @@ impl UniswapV3Factory {
         UniswapV3Factory {
             address,
             creation_block,
+            init_code_hash: None,
         }
     }
 
+    pub fn with_init_code_hash(mut self, init_code_hash: B256) -> Self {
+        self.init_code_hash = Some(init_code_hash);
+        self
+    }
+
+    /// Computes the address of the pool of `token_a` and `token_b` with `fee` without querying
+    /// the factory.
+    ///
+    /// Returns `None` if the init code hash of the factory is unknown.
+    pub fn pool_address(&self, token_a: Address, token_b: Address, fee: u32) -> Option<Address> {
+        let init_code_hash = self.init_code_hash?;
+        Some(compute_pool_address(
+            self.address,
+            init_code_hash,
+            token_a,
+            token_b,
+            fee,
+        ))
+    }
+
     // Function to get all pair created events for a given Dex factory address and sync pool data
     pub async fn get_all_pools_from_logs<N, P>(
         self,
@@ -164,52 +200,33 @@ impl UniswapV3Factory {
         N: Network,
         P: Provider<N> + Clone,
     {
-        // Unwrap can be used here because the creation block was verified within `Dex::new()`
-        let mut from_block = self.creation_block;
         let mut aggregated_amms: HashMap<Address, AMM> = HashMap::new();
         let mut ordered_logs: BTreeMap<u64, Vec<Log>> = BTreeMap::new();
-        let mut futures = FuturesOrdered::new();
-
-        while from_block < to_block {
-            let provider = provider.clone();
 
-            let mut target_block = from_block + step - 1;
-            if target_block > to_block {
-                target_block = to_block;
-            }
-
-            futures.push_back(async move {
-                provider
-                    .get_logs(
-                        &Filter::new()
-                            .event_signature(vec![
-                                IUniswapV3Factory::PoolCreated::SIGNATURE_HASH,
-                                IUniswapV3Pool::Burn::SIGNATURE_HASH,
-                                IUniswapV3Pool::Mint::SIGNATURE_HASH,
-                            ])
-                            .from_block(from_block)
-                            .to_block(target_block),
-                    )
-                    .await
-            });
-
-            from_block += step;
-        }
+        let filter = Filter::new().event_signature(vec![
+            IUniswapV3Factory::PoolCreated::SIGNATURE_HASH,
+            IUniswapV3Pool::Burn::SIGNATURE_HASH,
+            IUniswapV3Pool::Mint::SIGNATURE_HASH,
+        ]);
 
-        // TODO: this could be more dry since we use this in another place
-        while let Some(result) = futures.next().await {
-            let logs = result.map_err(AMMError::TransportError)?;
+        let logs = get_logs_in_chunks(
+            &filter,
+            self.creation_block,
+            to_block,
+            LogRangeConfig::new(step),
+            provider.clone(),
+        )
+        .await?;
 
-            for log in logs {
-                if let Some(log_block_number) = log.block_number {
-                    if let Some(log_group) = ordered_logs.get_mut(&log_block_number) {
-                        log_group.push(log);
-                    } else {
-                        ordered_logs.insert(log_block_number, vec![log]);
-                    }
+        for log in logs {
+            if let Some(log_block_number) = log.block_number {
+                if let Some(log_group) = ordered_logs.get_mut(&log_block_number) {
+                    log_group.push(log);
                 } else {
-                    return Err(EventLogError::LogBlockNumberNotFound)?;
+                    ordered_logs.insert(log_block_number, vec![log]);
                 }
+            } else {
+                return Err(EventLogError::LogBlockNumberNotFound)?;
             }
         }
 
@@ -245,3 +262,49 @@ impl UniswapV3Factory {
         Ok(aggregated_amms.into_values().collect::<Vec<AMM>>())
     }
 }
+
+/// Computes the CREATE2 address of the pool of `token_a` and `token_b` with `fee` deployed by
+/// `factory`.
+pub fn compute_pool_address(
+    factory: Address,
+    init_code_hash: B256,
+    token_a: Address,
+    token_b: Address,
+    fee: u32,
+) -> Address {
+    let (token_0, token_1) = if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    };
+
+    factory.create2(compute_pool_key_hash(token_0, token_1, fee), init_code_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::address;
+
+    #[test]
+    fn test_pool_address() {
+        let usdc = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+        let weth = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+
+        let factory = UniswapV3Factory::new(
+            address!("1F98431c8aD98523631AE4a59f267346ea31F984"),
+            12369621,
+        );
+        assert_eq!(factory.pool_address(usdc, weth, 500), None);
+
+        let factory = factory.with_init_code_hash(UNISWAP_V3_POOL_INIT_CODE_HASH);
+        assert_eq!(
+            factory.pool_address(weth, usdc, 500),
+            Some(address!("88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640"))
+        );
+        assert_eq!(
+            factory.pool_address(usdc, weth, 3000),
+            Some(address!("8ad599c3A0ff1De082011EFDDc58f1908eb6e6D8"))
+        );
+    }
+}
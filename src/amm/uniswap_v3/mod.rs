@@ -4,6 +4,7 @@ pub mod factory;
 use crate::{
     amm::{consts::*, AutomatedMarketMaker, IErc20},
     errors::{AMMError, ArithmeticError, EventLogError, SwapSimulationError},
+    rpc::get_logs::{get_logs_in_chunks, LogRangeConfig},
 };
 use alloy::{
     network::Network,
@@ -14,7 +15,6 @@ use alloy::{
     sol_types::{SolCall, SolEvent, SolValue},
 };
 use async_trait::async_trait;
-use futures::{stream::FuturesOrdered, StreamExt};
 use num_bigfloat::BigFloat;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -604,7 +604,7 @@ impl UniswapV3Pool {
     /// Returns the last synced block number.
     pub async fn populate_tick_data<N, P>(
         &mut self,
-        mut from_block: u64,
+        from_block: u64,
         provider: P,
     ) -> Result<u64, AMMError>
     where
@@ -616,52 +616,33 @@ impl UniswapV3Pool {
             .await
             .map_err(AMMError::TransportError)?;
 
-        let mut futures = FuturesOrdered::new();
-
         let mut ordered_logs: BTreeMap<u64, Vec<Log>> = BTreeMap::new();
 
-        let pool_address: Address = self.address;
-
-        while from_block <= current_block {
-            let provider = provider.clone();
-
-            let mut target_block = from_block + POPULATE_TICK_DATA_STEP - 1;
-            if target_block > current_block {
-                target_block = current_block;
-            }
-
-            futures.push_back(async move {
-                provider
-                    .get_logs(
-                        &Filter::new()
-                            .event_signature(vec![
-                                IUniswapV3Pool::Burn::SIGNATURE_HASH,
-                                IUniswapV3Pool::Mint::SIGNATURE_HASH,
-                            ])
-                            .address(pool_address)
-                            .from_block(from_block)
-                            .to_block(target_block),
-                    )
-                    .await
-            });
-
-            from_block += POPULATE_TICK_DATA_STEP;
-        }
-
-        // TODO: this could be more dry since we use this in another place
-        while let Some(result) = futures.next().await {
-            let logs = result.map_err(AMMError::TransportError)?;
+        let filter = Filter::new()
+            .event_signature(vec![
+                IUniswapV3Pool::Burn::SIGNATURE_HASH,
+                IUniswapV3Pool::Mint::SIGNATURE_HASH,
+            ])
+            .address(self.address);
+
+        let logs = get_logs_in_chunks(
+            &filter,
+            from_block,
+            current_block,
+            LogRangeConfig::new(POPULATE_TICK_DATA_STEP),
+            provider,
+        )
+        .await?;
 
-            for log in logs {
-                if let Some(log_block_number) = log.block_number {
-                    if let Some(log_group) = ordered_logs.get_mut(&log_block_number) {
-                        log_group.push(log);
-                    } else {
-                        ordered_logs.insert(log_block_number, vec![log]);
-                    }
+        for log in logs {
+            if let Some(log_block_number) = log.block_number {
+                if let Some(log_group) = ordered_logs.get_mut(&log_block_number) {
+                    log_group.push(log);
                 } else {
-                    return Err(AMMError::from(EventLogError::LogBlockNumberNotFound));
+                    ordered_logs.insert(log_block_number, vec![log]);
                 }
+            } else {
+                return Err(AMMError::from(EventLogError::LogBlockNumberNotFound));
             }
         }
 
@@ -0,0 +1,338 @@
+use std::time::Duration;
+
+use alloy::primitives::{address, Address, B256};
+
+use crate::amm::{
+    factory::Factory,
+    uniswap_v2::factory::{UniswapV2Factory, KNOWN_UNISWAP_V2_FORKS},
+    uniswap_v3::factory::{UniswapV3Factory, UNISWAP_V3_POOL_INIT_CODE_HASH},
+};
+
+pub const ETHEREUM_CHAIN_ID: u64 = 1;
+pub const OPTIMISM_CHAIN_ID: u64 = 10;
+pub const BASE_CHAIN_ID: u64 = 8453;
+pub const ARBITRUM_CHAIN_ID: u64 = 42161;
+
+/// Type of a known factory, with the parameters needed to build it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KnownFactoryKind {
+    UniswapV2 { fee: u32 },
+    UniswapV3,
+}
+
+/// A canonical factory deployment on a chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownFactory {
+    pub name: &'static str,
+    pub kind: KnownFactoryKind,
+    pub address: Address,
+    pub creation_block: u64,
+    pub init_code_hash: B256,
+}
+
+impl KnownFactory {
+    /// Returns the factory, ready to be synced.
+    pub fn to_factory(&self) -> Factory {
+        match self.kind {
+            KnownFactoryKind::UniswapV2 { fee } => Factory::UniswapV2Factory(
+                UniswapV2Factory::new(self.address, self.creation_block, fee)
+                    .with_init_code_hash(self.init_code_hash),
+            ),
+            KnownFactoryKind::UniswapV3 => Factory::UniswapV3Factory(
+                UniswapV3Factory::new(self.address, self.creation_block)
+                    .with_init_code_hash(self.init_code_hash),
+            ),
+        }
+    }
+}
+
+/// A widely traded token on a chain, commonly used as the base token of pools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BaseToken {
+    pub symbol: &'static str,
+    pub address: Address,
+    pub decimals: u8,
+}
+
+/// Well-known factories and base tokens of a chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chain {
+    pub chain_id: u64,
+    pub name: &'static str,
+    /// Average time between blocks
+    pub block_time: Duration,
+    /// Default number of recent blocks tracked to detect reorgs on the chain
+    pub reorg_depth: usize,
+    pub factories: &'static [KnownFactory],
+    pub base_tokens: &'static [BaseToken],
+}
+
+impl Chain {
+    /// Returns every known factory of the chain, ready to be passed to
+    /// [`sync_amms`](crate::sync::sync_amms).
+    pub fn factories(&self) -> Vec<Factory> {
+        self.factories
+            .iter()
+            .map(KnownFactory::to_factory)
+            .collect()
+    }
+
+    /// Returns the known factory named `name`, e.g. `"Uniswap V3"`.
+    pub fn factory(&self, name: &str) -> Option<&'static KnownFactory> {
+        self.factories.iter().find(|factory| factory.name == name)
+    }
+
+    /// Returns the base token with the symbol `symbol`, e.g. `"WETH"`.
+    pub fn base_token(&self, symbol: &str) -> Option<&'static BaseToken> {
+        self.base_tokens.iter().find(|token| token.symbol == symbol)
+    }
+}
+
+const UNISWAP_V2_INIT_CODE_HASH: B256 = KNOWN_UNISWAP_V2_FORKS[0].init_code_hash;
+const SUSHISWAP_INIT_CODE_HASH: B256 = KNOWN_UNISWAP_V2_FORKS[1].init_code_hash;
+
+pub const ETHEREUM: Chain = Chain {
+    chain_id: ETHEREUM_CHAIN_ID,
+    name: "Ethereum",
+    block_time: Duration::from_secs(12),
+    reorg_depth: 12,
+    factories: &[
+        KnownFactory {
+            name: "Uniswap V2",
+            kind: KnownFactoryKind::UniswapV2 { fee: 300 },
+            address: address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
+            creation_block: 10000835,
+            init_code_hash: UNISWAP_V2_INIT_CODE_HASH,
+        },
+        KnownFactory {
+            name: "SushiSwap",
+            kind: KnownFactoryKind::UniswapV2 { fee: 300 },
+            address: address!("C0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"),
+            creation_block: 10794229,
+            init_code_hash: SUSHISWAP_INIT_CODE_HASH,
+        },
+        KnownFactory {
+            name: "Uniswap V3",
+            kind: KnownFactoryKind::UniswapV3,
+            address: address!("1F98431c8aD98523631AE4a59f267346ea31F984"),
+            creation_block: 12369621,
+            init_code_hash: UNISWAP_V3_POOL_INIT_CODE_HASH,
+        },
+    ],
+    base_tokens: &[
+        BaseToken {
+            symbol: "WETH",
+            address: address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"),
+            decimals: 18,
+        },
+        BaseToken {
+            symbol: "USDC",
+            address: address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+            decimals: 6,
+        },
+        BaseToken {
+            symbol: "USDT",
+            address: address!("dAC17F958D2ee523a2206206994597C13D831ec7"),
+            decimals: 6,
+        },
+        BaseToken {
+            symbol: "DAI",
+            address: address!("6B175474E89094C44Da98b954EedeAC495271d0F"),
+            decimals: 18,
+        },
+        BaseToken {
+            symbol: "WBTC",
+            address: address!("2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599"),
+            decimals: 8,
+        },
+    ],
+};
+
+pub const OPTIMISM: Chain = Chain {
+    chain_id: OPTIMISM_CHAIN_ID,
+    name: "Optimism",
+    block_time: Duration::from_secs(2),
+    reorg_depth: 30,
+    factories: &[
+        // Deployed before the OVM regenesis, present from the genesis of the current chain
+        KnownFactory {
+            name: "Uniswap V3",
+            kind: KnownFactoryKind::UniswapV3,
+            address: address!("1F98431c8aD98523631AE4a59f267346ea31F984"),
+            creation_block: 0,
+            init_code_hash: UNISWAP_V3_POOL_INIT_CODE_HASH,
+        },
+    ],
+    base_tokens: &[
+        BaseToken {
+            symbol: "WETH",
+            address: address!("4200000000000000000000000000000000000006"),
+            decimals: 18,
+        },
+        BaseToken {
+            symbol: "USDC",
+            address: address!("0b2C639c533813f4Aa9D7837CAf62653d097Ff85"),
+            decimals: 6,
+        },
+        BaseToken {
+            symbol: "USDC.e",
+            address: address!("7F5c764cBc14f9669B88837ca1490cCa17c31607"),
+            decimals: 6,
+        },
+        BaseToken {
+            symbol: "USDT",
+            address: address!("94b008aA00579c1307B0EF2c499aD98a8ce58e58"),
+            decimals: 6,
+        },
+        BaseToken {
+            symbol: "DAI",
+            address: address!("DA10009cBd5D07dd0CeCc66161FC93D7c9000da1"),
+            decimals: 18,
+        },
+    ],
+};
+
+pub const BASE: Chain = Chain {
+    chain_id: BASE_CHAIN_ID,
+    name: "Base",
+    block_time: Duration::from_secs(2),
+    reorg_depth: 30,
+    factories: &[KnownFactory {
+        name: "Uniswap V3",
+        kind: KnownFactoryKind::UniswapV3,
+        address: address!("33128a8fC17869897dcE68Ed026d694621f6FDfD"),
+        creation_block: 1371680,
+        init_code_hash: UNISWAP_V3_POOL_INIT_CODE_HASH,
+    }],
+    base_tokens: &[
+        BaseToken {
+            symbol: "WETH",
+            address: address!("4200000000000000000000000000000000000006"),
+            decimals: 18,
+        },
+        BaseToken {
+            symbol: "USDC",
+            address: address!("833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"),
+            decimals: 6,
+        },
+    ],
+};
+
+pub const ARBITRUM: Chain = Chain {
+    chain_id: ARBITRUM_CHAIN_ID,
+    name: "Arbitrum One",
+    block_time: Duration::from_millis(250),
+    reorg_depth: 30,
+    factories: &[
+        KnownFactory {
+            name: "SushiSwap",
+            kind: KnownFactoryKind::UniswapV2 { fee: 300 },
+            address: address!("c35DADB65012eC5796536bD9864eD8773aBc74C4"),
+            creation_block: 70,
+            init_code_hash: SUSHISWAP_INIT_CODE_HASH,
+        },
+        KnownFactory {
+            name: "Uniswap V3",
+            kind: KnownFactoryKind::UniswapV3,
+            address: address!("1F98431c8aD98523631AE4a59f267346ea31F984"),
+            creation_block: 165,
+            init_code_hash: UNISWAP_V3_POOL_INIT_CODE_HASH,
+        },
+    ],
+    base_tokens: &[
+        BaseToken {
+            symbol: "WETH",
+            address: address!("82aF49447D8a07e3bd95BD0d56f35241523fBab1"),
+            decimals: 18,
+        },
+        BaseToken {
+            symbol: "USDC",
+            address: address!("af88d065e77c8cC2239327C5EDb3A432268e5831"),
+            decimals: 6,
+        },
+        BaseToken {
+            symbol: "USDC.e",
+            address: address!("FF970A61A04b1cA14834A43f5dE4533eBDDB5CC8"),
+            decimals: 6,
+        },
+        BaseToken {
+            symbol: "USDT",
+            address: address!("Fd086bC7CD5C481DCC9C85ebE478A1C0b69FCbb9"),
+            decimals: 6,
+        },
+        BaseToken {
+            symbol: "WBTC",
+            address: address!("2f2a2543B76A4166549F7aaB2e75Bef0aefC5B0f"),
+            decimals: 8,
+        },
+        BaseToken {
+            symbol: "DAI",
+            address: address!("DA10009cBd5D07dd0CeCc66161FC93D7c9000da1"),
+            decimals: 18,
+        },
+    ],
+};
+
+/// Every chain in the registry.
+pub static CHAINS: [Chain; 4] = [ETHEREUM, OPTIMISM, BASE, ARBITRUM];
+
+/// Returns the registry entry of the chain with id `chain_id`, if it is known.
+pub fn chain(chain_id: u64) -> Option<&'static Chain> {
+    CHAINS.iter().find(|chain| chain.chain_id == chain_id)
+}
+
+/// Returns the known factories of the chain with id `chain_id`, ready to be passed to
+/// [`sync_amms`](crate::sync::sync_amms). Unknown chains have no factories.
+pub fn factories(chain_id: u64) -> Vec<Factory> {
+    chain(chain_id).map(Chain::factories).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amm::factory::AutomatedMarketMakerFactory;
+
+    #[test]
+    fn test_chain_registry() {
+        let ethereum = chain(ETHEREUM_CHAIN_ID).unwrap();
+        assert_eq!(ethereum.name, "Ethereum");
+        assert!(chain(0).is_none());
+        assert!(factories(0).is_empty());
+
+        let factories = factories(ETHEREUM_CHAIN_ID);
+        assert_eq!(factories.len(), ethereum.factories.len());
+        assert_eq!(
+            factories[0].address(),
+            address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f")
+        );
+
+        // The registered init code hash computes the address of the USDC/WETH pair
+        let weth = ethereum.base_token("WETH").unwrap();
+        let usdc = ethereum.base_token("USDC").unwrap();
+        let Factory::UniswapV2Factory(uniswap_v2) = &factories[0] else {
+            panic!("expected a Uniswap V2 factory");
+        };
+        assert_eq!(
+            uniswap_v2.pair_address(usdc.address, weth.address),
+            Some(address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc"))
+        );
+
+        let uniswap_v3 = ethereum.factory("Uniswap V3").unwrap();
+        let Factory::UniswapV3Factory(uniswap_v3) = uniswap_v3.to_factory() else {
+            panic!("expected a Uniswap V3 factory");
+        };
+        assert_eq!(
+            uniswap_v3.pool_address(usdc.address, weth.address, 500),
+            Some(address!("88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640"))
+        );
+    }
+
+    #[test]
+    fn test_chain_ids_are_unique() {
+        for (i, chain) in CHAINS.iter().enumerate() {
+            assert!(CHAINS[i + 1..]
+                .iter()
+                .all(|other| other.chain_id != chain.chain_id));
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+
+use alloy::{
+    network::Network,
+    primitives::{address, Address, Bytes, B256},
+    providers::Provider,
+    rpc::types::eth::{Filter, Log},
+    sol,
+    sol_types::{SolCall, SolEvent},
+};
+
+use crate::{
+    amm::{balancer_v2::vault::BalancerV2VaultPool, AMM},
+    errors::AMMError,
+    rpc::{
+        get_logs::{get_logs_in_chunks, LogRangeConfig},
+        multicall,
+    },
+};
+
+/// Address of the Balancer V2 Vault, the same on every chain it is deployed on.
+pub const BALANCER_V2_VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+/// Block at which the Balancer V2 Vault was deployed on Ethereum mainnet only.
+///
+/// The Vault was deployed at a different block on every other chain, discovering pools there
+/// must start from the creation block of that chain.
+pub const BALANCER_V2_VAULT_CREATION_BLOCK: u64 = 12272146;
+
+/// Number of calls made to each pool to classify its type.
+const PROBE_CALLS: usize = 4;
+
+sol! {
+    /// Interface of the Balancer V2 Vault
+    #[derive(Debug, PartialEq, Eq)]
+    #[sol(rpc)]
+    contract IBalancerV2Vault {
+        event PoolRegistered(bytes32 indexed poolId, address indexed poolAddress, uint8 specialization);
+        event TokensRegistered(bytes32 indexed poolId, address[] tokens, address[] assetManagers);
+    }
+}
+
+sol! {
+    /// Functions specific to each type of Balancer V2 pool, used to classify pools
+    #[sol(rpc)]
+    contract IBalancerV2PoolTypes {
+        function getNormalizedWeights() external view returns (uint256[] memory);
+        function getAmplificationParameter() external view returns (uint256 value, bool isUpdating, uint256 precision);
+        function getBptIndex() external view returns (uint256);
+        function getMainToken() external view returns (address);
+    }
+}
+
+/// How the Vault stores the balances of a pool, see `IVault.PoolSpecialization`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolSpecialization {
+    General,
+    MinimalSwapInfo,
+    TwoToken,
+}
+
+impl TryFrom<u8> for PoolSpecialization {
+    type Error = AMMError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PoolSpecialization::General),
+            1 => Ok(PoolSpecialization::MinimalSwapInfo),
+            2 => Ok(PoolSpecialization::TwoToken),
+            _ => Err(AMMError::PoolDataError),
+        }
+    }
+}
+
+/// Type of a Balancer V2 pool, determined by the functions it implements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BalancerPoolType {
+    Weighted,
+    Stable,
+    /// Stable pool holding its own BPT as a token
+    ComposableStable,
+    Linear,
+    #[default]
+    Unknown,
+}
+
+impl BalancerPoolType {
+    /// Classifies a pool from the results of the probe calls made by [`probe_calls`].
+    fn from_return_data(return_data: &[Option<Bytes>; PROBE_CALLS]) -> Self {
+        let [weights, amplification, bpt_index, main_token] = return_data;
+
+        let implements = |return_data: &Option<Bytes>, decodes: fn(&[u8]) -> bool| {
+            return_data.as_ref().is_some_and(|data| decodes(data))
+        };
+
+        if implements(weights, |data| {
+            IBalancerV2PoolTypes::getNormalizedWeightsCall::abi_decode_returns(data, true).is_ok()
+        }) {
+            BalancerPoolType::Weighted
+        } else if implements(amplification, |data| {
+            IBalancerV2PoolTypes::getAmplificationParameterCall::abi_decode_returns(data, true)
+                .is_ok()
+        }) {
+            if implements(bpt_index, |data| {
+                IBalancerV2PoolTypes::getBptIndexCall::abi_decode_returns(data, true).is_ok()
+            }) {
+                BalancerPoolType::ComposableStable
+            } else {
+                BalancerPoolType::Stable
+            }
+        } else if implements(main_token, |data| {
+            IBalancerV2PoolTypes::getMainTokenCall::abi_decode_returns(data, true).is_ok()
+        }) {
+            BalancerPoolType::Linear
+        } else {
+            BalancerPoolType::Unknown
+        }
+    }
+}
+
+/// A pool registered with the Balancer V2 Vault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultPool {
+    pub pool_id: B256,
+    pub address: Address,
+    pub specialization: PoolSpecialization,
+    pub pool_type: BalancerPoolType,
+    /// Tokens registered for the pool, in registration order
+    pub tokens: Vec<Address>,
+    pub registered_block: u64,
+}
+
+impl VaultPool {
+    /// Returns an empty AMM for the pool, ready to be populated.
+    ///
+    /// Only weighted pools are supported, `None` is returned for every other pool type.
+    pub fn to_amm(&self) -> Option<AMM> {
+        (self.pool_type == BalancerPoolType::Weighted).then(|| {
+            AMM::BalancerV2VaultPool(BalancerV2VaultPool::new(
+                self.address,
+                self.pool_id,
+                self.tokens.clone(),
+            ))
+        })
+    }
+}
+
+/// Discovers the pools registered with the Balancer V2 `vault` from `from_block` to the latest
+/// block.
+///
+/// Pools and their tokens are read from the `PoolRegistered` and `TokensRegistered` events, in
+/// block ranges of `step` blocks scanned concurrently. The type of each pool is then classified
+/// with a batched probe of the functions specific to each pool type.
+pub async fn discover_balancer_v2_pools<N, P>(
+    vault: Address,
+    from_block: u64,
+    step: u64,
+    provider: P,
+) -> Result<Vec<VaultPool>, AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let filter = Filter::new().address(vault).event_signature(vec![
+        IBalancerV2Vault::PoolRegistered::SIGNATURE_HASH,
+        IBalancerV2Vault::TokensRegistered::SIGNATURE_HASH,
+    ]);
+
+    let current_block = provider.get_block_number().await?;
+
+    let logs = get_logs_in_chunks(
+        &filter,
+        from_block,
+        current_block,
+        LogRangeConfig::new(step),
+        provider.clone(),
+    )
+    .await?;
+
+    let mut pools = vault_pools_from_logs(logs)?;
+
+    let calls = pools
+        .iter()
+        .flat_map(|pool| probe_calls(pool.address))
+        .collect();
+    let return_data = multicall::try_aggregate(calls, Some(current_block), provider).await?;
+
+    for (pool, return_data) in pools.iter_mut().zip(return_data.chunks_exact(PROBE_CALLS)) {
+        let return_data = return_data.try_into().expect("Chunks should be exact");
+        pool.pool_type = BalancerPoolType::from_return_data(return_data);
+    }
+
+    tracing::debug!(pools = pools.len(), "discovered Balancer V2 pools");
+
+    Ok(pools)
+}
+
+/// Builds the registered pools from the Vault logs, in registration order.
+///
+/// Tokens registered for a pool whose registration is not in `logs` are ignored, as are pools
+/// registered with an unknown specialization.
+fn vault_pools_from_logs(logs: Vec<Log>) -> Result<Vec<VaultPool>, AMMError> {
+    let mut pools = vec![];
+    let mut pool_indices = HashMap::new();
+
+    for log in logs {
+        let Some(signature) = log.topics().first() else {
+            continue;
+        };
+
+        if *signature == IBalancerV2Vault::PoolRegistered::SIGNATURE_HASH {
+            let registered_block = log.block_number.ok_or(AMMError::BlockNumberNotFound)?;
+            let event = IBalancerV2Vault::PoolRegistered::decode_log(log.as_ref(), true)?;
+
+            let Ok(specialization) = event.specialization.try_into() else {
+                tracing::warn!(
+                    pool = ?event.poolAddress,
+                    specialization = event.specialization,
+                    "skipping pool with unknown specialization"
+                );
+                continue;
+            };
+
+            pool_indices.insert(event.poolId, pools.len());
+            pools.push(VaultPool {
+                pool_id: event.poolId,
+                address: event.poolAddress,
+                specialization,
+                pool_type: BalancerPoolType::Unknown,
+                tokens: vec![],
+                registered_block,
+            });
+        } else if *signature == IBalancerV2Vault::TokensRegistered::SIGNATURE_HASH {
+            let event = IBalancerV2Vault::TokensRegistered::decode_log(log.as_ref(), true)?;
+
+            if let Some(&idx) = pool_indices.get(&event.poolId) {
+                pools[idx].tokens.extend(event.data.tokens);
+            }
+        }
+    }
+
+    Ok(pools)
+}
+
+/// Returns the calls classifying the type of `pool`, in the order expected by
+/// [`BalancerPoolType::from_return_data`].
+fn probe_calls(pool: Address) -> [(Address, Bytes); PROBE_CALLS] {
+    [
+        IBalancerV2PoolTypes::getNormalizedWeightsCall {}.abi_encode(),
+        IBalancerV2PoolTypes::getAmplificationParameterCall {}.abi_encode(),
+        IBalancerV2PoolTypes::getBptIndexCall {}.abi_encode(),
+        IBalancerV2PoolTypes::getMainTokenCall {}.abi_encode(),
+    ]
+    .map(|call_data| (pool, call_data.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::{
+        primitives::{LogData, U256},
+        sol_types::SolValue,
+    };
+
+    fn vault_log<E: SolEvent>(event: &E, block_number: u64) -> Log {
+        Log {
+            inner: alloy::primitives::Log {
+                address: BALANCER_V2_VAULT,
+                data: event.encode_log_data(),
+            },
+            block_number: Some(block_number),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_vault_pools_from_logs() {
+        let pool_id = B256::with_last_byte(1);
+        let pool_address = Address::with_last_byte(1);
+        let tokens = vec![Address::with_last_byte(2), Address::with_last_byte(3)];
+
+        let logs = vec![
+            vault_log(
+                &IBalancerV2Vault::PoolRegistered {
+                    poolId: pool_id,
+                    poolAddress: pool_address,
+                    specialization: 2,
+                },
+                1,
+            ),
+            vault_log(
+                &IBalancerV2Vault::TokensRegistered {
+                    poolId: pool_id,
+                    tokens: tokens.clone(),
+                    assetManagers: vec![Address::ZERO; 2],
+                },
+                1,
+            ),
+            // Tokens of a pool registered before the scanned range
+            vault_log(
+                &IBalancerV2Vault::TokensRegistered {
+                    poolId: B256::with_last_byte(2),
+                    tokens: tokens.clone(),
+                    assetManagers: vec![Address::ZERO; 2],
+                },
+                2,
+            ),
+            Log {
+                inner: alloy::primitives::Log {
+                    address: BALANCER_V2_VAULT,
+                    data: LogData::new_unchecked(vec![], Bytes::new()),
+                },
+                ..Default::default()
+            },
+            // Pools with an unknown specialization are skipped
+            vault_log(
+                &IBalancerV2Vault::PoolRegistered {
+                    poolId: B256::with_last_byte(3),
+                    poolAddress: Address::with_last_byte(4),
+                    specialization: 3,
+                },
+                3,
+            ),
+        ];
+
+        let pools = vault_pools_from_logs(logs).unwrap();
+        assert_eq!(pools.len(), 1);
+        assert_eq!(pools[0].address, pool_address);
+        assert_eq!(pools[0].specialization, PoolSpecialization::TwoToken);
+        assert_eq!(pools[0].tokens, tokens);
+        assert_eq!(pools[0].registered_block, 1);
+        // The pool type is classified by probing
+        assert_eq!(pools[0].pool_type, BalancerPoolType::Unknown);
+        assert!(pools[0].to_amm().is_none());
+    }
+
+    #[test]
+    fn test_to_amm() {
+        let pool = VaultPool {
+            pool_id: B256::with_last_byte(1),
+            address: Address::with_last_byte(1),
+            specialization: PoolSpecialization::TwoToken,
+            pool_type: BalancerPoolType::Weighted,
+            tokens: vec![Address::with_last_byte(2), Address::with_last_byte(3)],
+            registered_block: 1,
+        };
+
+        let Some(AMM::BalancerV2VaultPool(amm)) = pool.to_amm() else {
+            panic!("weighted pools should convert to a Vault pool AMM");
+        };
+        assert_eq!(amm.address, pool.address);
+        assert_eq!(amm.pool_id, pool.pool_id);
+        assert_eq!(amm.tokens, pool.tokens);
+
+        let stable_pool = VaultPool {
+            pool_type: BalancerPoolType::Stable,
+            ..pool
+        };
+        assert!(stable_pool.to_amm().is_none());
+    }
+
+    #[test]
+    fn test_classify_pool_type() {
+        let weights = Some(Bytes::from(vec![U256::from(5), U256::from(5)].abi_encode()));
+        let amplification = Some(Bytes::from(
+            (U256::from(200), false, U256::from(1000)).abi_encode_params(),
+        ));
+        let bpt_index = Some(Bytes::from(U256::from(1).abi_encode()));
+        let main_token = Some(Bytes::from(Address::with_last_byte(1).abi_encode()));
+
+        let classify = |return_data: [Option<Bytes>; PROBE_CALLS]| {
+            BalancerPoolType::from_return_data(&return_data)
+        };
+
+        assert_eq!(
+            classify([weights, None, None, None]),
+            BalancerPoolType::Weighted
+        );
+        assert_eq!(
+            classify([None, amplification.clone(), None, None]),
+            BalancerPoolType::Stable
+        );
+        assert_eq!(
+            classify([None, amplification, bpt_index, None]),
+            BalancerPoolType::ComposableStable
+        );
+        assert_eq!(
+            classify([None, None, None, main_token]),
+            BalancerPoolType::Linear
+        );
+        assert_eq!(
+            classify([None, None, None, None]),
+            BalancerPoolType::Unknown
+        );
+    }
+}
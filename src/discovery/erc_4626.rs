@@ -1,25 +1,68 @@
-use std::{collections::HashSet, str::FromStr};
+use std::collections::HashSet;
 
 use alloy::{
-    network::Network, primitives::U256, providers::Provider, rpc::types::eth::Filter,
-    sol_types::SolEvent,
+    network::Network,
+    primitives::{Address, Bytes, U256},
+    providers::Provider,
+    rpc::types::eth::{Filter, Log},
+    sol_types::{SolCall, SolEvent},
 };
-use regex::Regex;
+use futures::stream::{self, StreamExt};
+use thiserror::Error;
 
 use crate::{
-    amm::erc_4626::{ERC4626Vault, IERC4626Vault},
+    amm::{
+        erc_4626::{ERC4626Vault, IERC4626Vault},
+        AutomatedMarketMaker,
+    },
     errors::AMMError,
+    rpc::{
+        get_logs::{get_logs_in_chunks, LogRangeConfig, DEFAULT_MAX_CONCURRENT_REQUESTS},
+        multicall,
+    },
 };
 
-lazy_static::lazy_static! {
-    static ref HEX_REGEX: Regex = Regex::new(r"0x[0-9a-fA-F]+").expect("Could not compile regex");
+/// Number of calls made to each candidate to verify that it implements ERC4626.
+const PROBE_CALLS: usize = 4;
+
+/// Why an address emitting ERC4626 events was not returned as a vault.
+#[derive(Error, Debug)]
+pub enum VaultRejection {
+    #[error("Emits Deposit events but no Withdraw events")]
+    NoWithdrawEvents,
+    #[error("Emits Withdraw events but no Deposit events")]
+    NoDepositEvents,
+    #[error("{0} reverted or returned invalid data")]
+    InterfaceMismatch(&'static str),
+    #[error(transparent)]
+    AMMError(#[from] AMMError),
+}
+
+#[derive(Debug)]
+pub struct RejectedVault {
+    pub address: Address,
+    pub reason: VaultRejection,
+}
+
+#[derive(Debug, Default)]
+pub struct ERC4626Discovery {
+    pub vaults: Vec<ERC4626Vault>,
+    /// Addresses that emitted ERC4626 events but are not supported vaults
+    pub rejected: Vec<RejectedVault>,
 }
 
-// Returns a vec of empty factories that match one of the Factory interfaces specified by each DiscoverableFactory
+/// Discovers ERC4626 vaults from the `Deposit` and `Withdraw` events emitted from `from_block` to
+/// the latest block.
+///
+/// Block ranges of `step` blocks are scanned concurrently. Addresses emitting both events are
+/// verified with a batched probe of `asset()`, `totalAssets()`, `convertToShares()` and
+/// `decimals()` before their data is populated, both at the latest scanned block. Every other
+/// address is returned as rejected with the reason.
 pub async fn discover_erc_4626_vaults<N, P>(
     provider: P,
+    from_block: u64,
     step: u64,
-) -> Result<Vec<ERC4626Vault>, AMMError>
+) -> Result<ERC4626Discovery, AMMError>
 where
     N: Network,
     P: Provider<N> + Clone,
@@ -33,89 +76,283 @@ where
 
     let current_block = provider.get_block_number().await?;
 
-    let mut adheres_to_withdraw_event = HashSet::new();
-    let mut adheres_to_deposit_event = HashSet::new();
-    let mut identified_addresses = HashSet::new();
-
-    let mut from_block = 0;
-    // TODO: make this async
-    while from_block < current_block {
-        // Get pair created event logs within the block range
-        let mut to_block = from_block + step - 1;
-        if to_block > current_block {
-            to_block = current_block;
+    let logs = get_logs_in_chunks(
+        &block_filter,
+        from_block,
+        current_block,
+        LogRangeConfig::new(step),
+        provider.clone(),
+    )
+    .await?;
+
+    let (candidates, mut rejected) = event_candidates(&logs);
+
+    // Probe every candidate at the scanned block so that the results match the logs
+    let calls = candidates
+        .iter()
+        .flat_map(|address| probe_calls(*address))
+        .collect();
+    let return_data =
+        multicall::try_aggregate(calls, Some(current_block), provider.clone()).await?;
+
+    // Every candidate must get a result for each probe, zipping would silently drop the rest
+    if return_data.len() != candidates.len() * PROBE_CALLS {
+        return Err(AMMError::BatchLengthMismatch {
+            expected: candidates.len() * PROBE_CALLS,
+            returned: return_data.len(),
+        });
+    }
+
+    let mut verified = vec![];
+    for (address, return_data) in candidates
+        .into_iter()
+        .zip(return_data.chunks_exact(PROBE_CALLS))
+    {
+        let return_data = return_data.try_into().expect("Chunks should be exact");
+        match verify_interface(return_data) {
+            Ok(()) => verified.push(address),
+            Err(reason) => rejected.push(RejectedVault { address, reason }),
         }
+    }
 
-        let block_filter = block_filter.clone();
-        // TODO: use a better method, this is just quick and scrappy
-        let fallback_block_filter = block_filter.clone();
-
-        let logs = match provider
-            .get_logs(&block_filter.from_block(from_block).to_block(to_block))
-            .await
-        {
-            Ok(logs) => {
-                from_block += step;
-                logs
-            }
-            Err(err) => {
-                tracing::warn!("error getting logs");
-
-                let mut block_range = Vec::new();
-                for m in HEX_REGEX.find_iter(&err.to_string()) {
-                    let value = U256::from_str(m.as_str()).map_err(|_| AMMError::FromHexError)?;
-                    block_range.push(value);
-                }
-
-                if block_range.is_empty() {
-                    return Err(AMMError::TransportError(err));
-                } else {
-                    tracing::warn!(
-                        "getting logs from blocks {}-{} instead",
-                        block_range[0],
-                        block_range[1]
-                    );
-                    let logs = provider
-                        .get_logs(
-                            &fallback_block_filter
-                                .from_block(block_range[0].to::<u64>())
-                                .to_block(block_range[1].to::<u64>()),
-                        )
-                        .await?;
-
-                    from_block = block_range[1].to::<u64>();
-
-                    logs
-                }
+    let populated = stream::iter(verified)
+        .map(|address| {
+            let provider = provider.clone();
+            async move {
+                (
+                    address,
+                    populate_vault(address, current_block, provider).await,
+                )
             }
+        })
+        .buffer_unordered(DEFAULT_MAX_CONCURRENT_REQUESTS)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut vaults = vec![];
+    for (address, result) in populated {
+        match result {
+            Ok(vault) => vaults.push(vault),
+            Err(err) => rejected.push(RejectedVault {
+                address,
+                reason: err.into(),
+            }),
+        }
+    }
+
+    for RejectedVault { address, reason } in rejected.iter() {
+        tracing::debug!(?address, %reason, "rejected ERC4626 vault candidate");
+    }
+
+    Ok(ERC4626Discovery { vaults, rejected })
+}
+
+/// Populates the vault at `address` at `block_number`, the block the candidates were probed at.
+async fn populate_vault<N, P>(
+    address: Address,
+    block_number: u64,
+    provider: P,
+) -> Result<ERC4626Vault, AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let mut vault = ERC4626Vault {
+        vault_token: address,
+        ..Default::default()
+    };
+
+    vault.populate_data(Some(block_number), provider).await?;
+
+    if !vault.data_is_populated() {
+        return Err(AMMError::PoolDataError);
+    }
+
+    Ok(vault)
+}
+
+/// Splits the addresses emitting ERC4626 events into candidates emitting both `Deposit` and
+/// `Withdraw` events and rejected addresses emitting only one of them, both sorted by address.
+fn event_candidates(logs: &[Log]) -> (Vec<Address>, Vec<RejectedVault>) {
+    let mut adheres_to_deposit_event = HashSet::new();
+    let mut adheres_to_withdraw_event = HashSet::new();
+
+    for log in logs {
+        let Some(signature) = log.topics().first() else {
+            continue;
         };
 
-        for log in logs {
-            if log.topics()[0] == IERC4626Vault::Deposit::SIGNATURE_HASH {
-                adheres_to_deposit_event.insert(log.address());
-            } else if log.topics()[0] == IERC4626Vault::Withdraw::SIGNATURE_HASH {
-                adheres_to_withdraw_event.insert(log.address());
-            }
+        if *signature == IERC4626Vault::Deposit::SIGNATURE_HASH {
+            adheres_to_deposit_event.insert(log.address());
+        } else if *signature == IERC4626Vault::Withdraw::SIGNATURE_HASH {
+            adheres_to_withdraw_event.insert(log.address());
         }
     }
 
-    for address in adheres_to_deposit_event.iter() {
-        if adheres_to_withdraw_event.contains(address) {
-            identified_addresses.insert(address);
+    let mut addresses = adheres_to_deposit_event
+        .union(&adheres_to_withdraw_event)
+        .copied()
+        .collect::<Vec<_>>();
+    addresses.sort();
+
+    let mut candidates = vec![];
+    let mut rejected = vec![];
+    for address in addresses {
+        match (
+            adheres_to_deposit_event.contains(&address),
+            adheres_to_withdraw_event.contains(&address),
+        ) {
+            (true, true) => candidates.push(address),
+            (true, false) => rejected.push(RejectedVault {
+                address,
+                reason: VaultRejection::NoWithdrawEvents,
+            }),
+            _ => rejected.push(RejectedVault {
+                address,
+                reason: VaultRejection::NoDepositEvents,
+            }),
         }
     }
 
-    let mut vaults = vec![];
-    for identified_address in identified_addresses {
-        //TODO: Add an interface check, but for now just try to get a new vault from address, if it fails then do not add it to the identified
-        //TODO: vaults. This approach is inefficient but should work for now.
-
-        if let Ok(vault) =
-            ERC4626Vault::new_from_address(*identified_address, provider.clone()).await
-        {
-            vaults.push(vault);
+    (candidates, rejected)
+}
+
+/// Returns the calls verifying that `vault` implements ERC4626, in the order expected by
+/// [`verify_interface`].
+fn probe_calls(vault: Address) -> [(Address, Bytes); PROBE_CALLS] {
+    [
+        IERC4626Vault::assetCall {}.abi_encode(),
+        IERC4626Vault::totalAssetsCall {}.abi_encode(),
+        IERC4626Vault::convertToSharesCall {
+            assets: U256::from(1),
         }
+        .abi_encode(),
+        IERC4626Vault::decimalsCall {}.abi_encode(),
+    ]
+    .map(|call_data| (vault, call_data.into()))
+}
+
+/// Checks that every probe call succeeded and returned data of the expected type.
+fn verify_interface(return_data: &[Option<Bytes>; PROBE_CALLS]) -> Result<(), VaultRejection> {
+    let [asset, total_assets, convert_to_shares, decimals] = return_data;
+
+    let asset = decode_return::<IERC4626Vault::assetCall>(asset)?;
+    if asset._0.is_zero() {
+        return Err(VaultRejection::InterfaceMismatch(
+            IERC4626Vault::assetCall::SIGNATURE,
+        ));
     }
 
-    Ok(vaults)
+    decode_return::<IERC4626Vault::totalAssetsCall>(total_assets)?;
+    decode_return::<IERC4626Vault::convertToSharesCall>(convert_to_shares)?;
+    decode_return::<IERC4626Vault::decimalsCall>(decimals)?;
+
+    Ok(())
+}
+
+fn decode_return<C: SolCall>(return_data: &Option<Bytes>) -> Result<C::Return, VaultRejection> {
+    return_data
+        .as_ref()
+        .and_then(|data| C::abi_decode_returns(data, true).ok())
+        .ok_or(VaultRejection::InterfaceMismatch(C::SIGNATURE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::{primitives::LogData, sol_types::SolValue};
+
+    fn event_log(address: Address, signature: alloy::primitives::B256) -> Log {
+        Log {
+            inner: alloy::primitives::Log {
+                address,
+                data: LogData::new_unchecked(vec![signature], Bytes::new()),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_event_candidates() {
+        let vault = Address::with_last_byte(1);
+        let deposit_only = Address::with_last_byte(2);
+        let withdraw_only = Address::with_last_byte(3);
+
+        let logs = vec![
+            event_log(vault, IERC4626Vault::Deposit::SIGNATURE_HASH),
+            event_log(deposit_only, IERC4626Vault::Deposit::SIGNATURE_HASH),
+            event_log(vault, IERC4626Vault::Withdraw::SIGNATURE_HASH),
+            event_log(withdraw_only, IERC4626Vault::Withdraw::SIGNATURE_HASH),
+        ];
+
+        let (candidates, rejected) = event_candidates(&logs);
+        assert_eq!(candidates, vec![vault]);
+        assert_eq!(rejected.len(), 2);
+        assert!(matches!(
+            rejected[0],
+            RejectedVault {
+                address,
+                reason: VaultRejection::NoWithdrawEvents
+            } if address == deposit_only
+        ));
+        assert!(matches!(
+            rejected[1],
+            RejectedVault {
+                address,
+                reason: VaultRejection::NoDepositEvents
+            } if address == withdraw_only
+        ));
+    }
+
+    #[test]
+    fn test_verify_interface() {
+        let word = |value: U256| Some(Bytes::from(value.abi_encode()));
+        let asset = Some(Bytes::from(Address::with_last_byte(1).abi_encode()));
+
+        let return_data = [
+            asset.clone(),
+            word(U256::from(100)),
+            word(U256::from(1)),
+            word(U256::from(18)),
+        ];
+        assert!(verify_interface(&return_data).is_ok());
+
+        // A reverted call
+        let return_data = [
+            asset.clone(),
+            word(U256::from(100)),
+            None,
+            word(U256::from(18)),
+        ];
+        assert!(matches!(
+            verify_interface(&return_data),
+            Err(VaultRejection::InterfaceMismatch(
+                "convertToShares(uint256)"
+            ))
+        ));
+
+        // decimals() returning a value that does not fit a uint8
+        let return_data = [
+            asset,
+            word(U256::from(100)),
+            word(U256::from(1)),
+            word(U256::from(256)),
+        ];
+        assert!(matches!(
+            verify_interface(&return_data),
+            Err(VaultRejection::InterfaceMismatch("decimals()"))
+        ));
+
+        // A zero asset
+        let return_data = [
+            word(U256::ZERO),
+            word(U256::from(100)),
+            word(U256::from(1)),
+            word(U256::from(18)),
+        ];
+        assert!(matches!(
+            verify_interface(&return_data),
+            Err(VaultRejection::InterfaceMismatch("asset()"))
+        ));
+    }
 }
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use futures::stream::{FuturesUnordered, StreamExt};
+use futures::stream::{self, StreamExt};
 
 use alloy::{
     network::Network,
@@ -12,10 +12,16 @@ use alloy::{
 
 use crate::{
     amm::{
-        balancer_v2::factory::IBFactory, factory::Factory, uniswap_v2::factory::IUniswapV2Factory,
+        balancer_v2::factory::IBFactory,
+        factory::Factory,
+        uniswap_v2::factory::{IUniswapV2Factory, DEFAULT_UNISWAP_V2_FEE},
         uniswap_v3::factory::IUniswapV3Factory,
     },
     errors::AMMError,
+    rpc::get_logs::{
+        get_logs_adaptive, AdaptiveLogRange, LogRangeConfig, DEFAULT_MAX_CONCURRENT_REQUESTS,
+    },
+    sync::events::{self, SyncEvent},
 };
 
 pub enum DiscoverableFactory {
@@ -72,22 +78,21 @@ where
         from_block += block_step;
     }
 
-    // Create futures unordered
-    let factories_tasks = FuturesUnordered::new();
     // Set up filter and events to filter each block you are searching by
     let block_filter = Filter::new().event_signature(event_signatures);
 
-    // Push task to futures unordered
-    for (from_block, target_block) in block_num_vec {
-        let block_filter = block_filter.clone();
-        let provider = provider.clone();
-        factories_tasks.push(async move {
-            process_block_logs_batch(&from_block, &target_block, provider, &block_filter).await
-        });
-    }
-
-    // collect the results when they are finished
-    let factory_results = factories_tasks.collect::<Vec<_>>().await;
+    // Request at most `DEFAULT_MAX_CONCURRENT_REQUESTS` block ranges at once
+    let factory_results = stream::iter(block_num_vec)
+        .map(|(from_block, target_block)| {
+            let block_filter = block_filter.clone();
+            let provider = provider.clone();
+            async move {
+                process_block_logs_batch(&from_block, &target_block, provider, &block_filter).await
+            }
+        })
+        .buffer_unordered(DEFAULT_MAX_CONCURRENT_REQUESTS)
+        .collect::<Vec<_>>()
+        .await;
 
     // process resulst
     let mut identified_factories: HashMap<Address, (Factory, u64)> = HashMap::new();
@@ -119,6 +124,28 @@ where
         }
     }
 
+    // Uniswap V2 forks do not emit their fee, detect it from their pairs
+    let filtered_factories = stream::iter(filtered_factories)
+        .map(|mut factory| {
+            let provider = provider.clone();
+            async move {
+                if let Factory::UniswapV2Factory(uniswap_v2_factory) = &mut factory {
+                    if let Err(err) = uniswap_v2_factory.detect_fork(provider).await {
+                        tracing::warn!(factory = ?uniswap_v2_factory.address, ?err, "failed to detect Uniswap V2 fork, using default fee");
+                        uniswap_v2_factory.fee = DEFAULT_UNISWAP_V2_FEE;
+                    }
+                }
+                factory
+            }
+        })
+        .buffer_unordered(DEFAULT_MAX_CONCURRENT_REQUESTS)
+        .collect::<Vec<_>>()
+        .await;
+
+    events::emit(SyncEvent::FactoriesDiscovered {
+        factories: filtered_factories.len(),
+    });
+
     Ok(filtered_factories)
 }
 
@@ -132,12 +159,17 @@ where
     N: Network,
     P: Provider<N> + Clone,
 {
-    let block_filter = block_filter.clone();
     let mut local_identified_factories: HashMap<Address, (Factory, u64)> = HashMap::new();
 
-    let logs = provider
-        .get_logs(&block_filter.from_block(*from_block).to_block(*target_block))
-        .await?;
+    let mut range = AdaptiveLogRange::new(LogRangeConfig::new(target_block - from_block + 1));
+    let logs = get_logs_adaptive(
+        block_filter,
+        *from_block,
+        *target_block,
+        &mut range,
+        provider,
+    )
+    .await?;
 
     for log in logs {
         if let Some((_, amms_length)) = local_identified_factories.get_mut(&log.address()) {
@@ -1,2 +1,3 @@
+pub mod balancer_v2;
 pub mod erc_4626;
 pub mod factory;
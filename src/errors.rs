@@ -44,6 +44,8 @@ pub enum AMMError {
     IncongruentAMMs,
     #[error("Invalid ERC4626 fee")]
     InvalidERC4626Fee,
+    #[error("Amount {amount} exceeds the ERC4626 vault limit {limit}")]
+    ERC4626LimitExceeded { amount: U256, limit: U256 },
     #[error(transparent)]
     EventLogError(#[from] EventLogError),
     #[error("Block number not found")]
@@ -52,6 +54,8 @@ pub enum AMMError {
     SwapSimulationError(#[from] SwapSimulationError),
     #[error("Invalid data from batch request")]
     BatchRequestError(Address),
+    #[error("Expected {expected} results from the batch request, got {returned}")]
+    BatchLengthMismatch { expected: usize, returned: usize },
     #[error(transparent)]
     CheckpointError(#[from] CheckpointError),
     #[error(transparent)]
@@ -28,6 +28,11 @@ pub fn filter_empty_amms(amms: Vec<AMM>) -> Vec<AMM> {
                     cleaned_amms.push(amm)
                 }
             }
+            AMM::BalancerV2VaultPool(ref balancer_v2_vault_pool) => {
+                if !balancer_v2_vault_pool.tokens.is_empty() {
+                    cleaned_amms.push(amm)
+                }
+            }
         }
     }
 
@@ -1,9 +1,12 @@
 #![cfg_attr(not(test), warn(unused_crate_dependencies))]
 
 pub mod amm;
+pub mod chains;
 pub mod discovery;
 pub mod errors;
 pub mod filters;
+pub mod rpc;
 #[cfg(feature = "state-space")]
 pub mod state_space;
 pub mod sync;
+pub mod tokens;
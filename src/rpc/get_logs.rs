@@ -0,0 +1,519 @@
+use std::{collections::BTreeMap, str::FromStr, time::Duration};
+
+use alloy::{
+    network::Network,
+    primitives::U256,
+    providers::Provider,
+    rpc::types::eth::{Filter, Log},
+    transports::{RpcError, TransportError},
+};
+use futures::stream::{FuturesUnordered, StreamExt};
+use regex::Regex;
+use tokio::sync::Mutex;
+
+use crate::{
+    errors::AMMError,
+    sync::events::{self, SyncEvent},
+};
+
+lazy_static::lazy_static! {
+    static ref HEX_REGEX: Regex = Regex::new(r"0x[0-9a-fA-F]+").expect("Could not compile regex");
+}
+
+/// Default number of times a transient `eth_getLogs` failure is retried before giving up.
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+/// Default delay before the first retry of a transient failure.
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+/// Default upper bound for the exponential retry delay.
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+/// Default number of block ranges requested concurrently.
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 16;
+
+/// Bounds and retry policy used when requesting logs over a block range.
+///
+/// Steps are always at least one block and `min_step <= initial_step <= max_step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogRangeConfig {
+    /// Number of blocks requested per call before any adjustment.
+    initial_step: u64,
+    /// Smallest block range the controller will shrink to.
+    min_step: u64,
+    /// Largest block range the controller will grow to.
+    max_step: u64,
+    /// Number of consecutive transient failures tolerated for a single range.
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    /// Maximum number of block ranges requested at once by [`get_logs_in_chunks`].
+    max_concurrent_requests: usize,
+}
+
+impl LogRangeConfig {
+    pub fn new(initial_step: u64) -> Self {
+        let initial_step = initial_step.max(1);
+
+        LogRangeConfig {
+            initial_step,
+            min_step: 1,
+            max_step: initial_step.saturating_mul(4),
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+        }
+    }
+
+    /// Sets the smallest and largest block ranges requested.
+    ///
+    /// Both bounds are at least one block, a `max_step` below `min_step` is raised to it, and the
+    /// initial step is clamped to the bounds.
+    pub fn with_step_bounds(mut self, min_step: u64, max_step: u64) -> Self {
+        self.min_step = min_step.max(1);
+        self.max_step = max_step.max(self.min_step);
+        self.initial_step = self.initial_step.clamp(self.min_step, self.max_step);
+        self
+    }
+
+    /// Sets the number of retries of a transient failure and the bounds of the exponential
+    /// backoff between them.
+    pub fn with_retries(
+        mut self,
+        max_retries: u32,
+        initial_backoff: Duration,
+        max_backoff: Duration,
+    ) -> Self {
+        self.max_retries = max_retries;
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff.max(initial_backoff);
+        self
+    }
+
+    /// Sets the number of block ranges requested at once, at least one.
+    pub fn with_max_concurrent_requests(mut self, max_concurrent_requests: usize) -> Self {
+        self.max_concurrent_requests = max_concurrent_requests.max(1);
+        self
+    }
+
+    pub fn initial_step(&self) -> u64 {
+        self.initial_step
+    }
+
+    pub fn min_step(&self) -> u64 {
+        self.min_step
+    }
+
+    pub fn max_step(&self) -> u64 {
+        self.max_step
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    pub fn initial_backoff(&self) -> Duration {
+        self.initial_backoff
+    }
+
+    pub fn max_backoff(&self) -> Duration {
+        self.max_backoff
+    }
+
+    pub fn max_concurrent_requests(&self) -> usize {
+        self.max_concurrent_requests
+    }
+}
+
+impl From<u64> for LogRangeConfig {
+    fn from(initial_step: u64) -> Self {
+        LogRangeConfig::new(initial_step)
+    }
+}
+
+/// How a failed `eth_getLogs` request should be handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogErrorKind {
+    /// The provider rejected the request because the range or result set is too large.
+    /// Some providers suggest a block range that will succeed.
+    RangeTooLarge { suggested_range: Option<(u64, u64)> },
+    /// The request may succeed if retried as is (rate limits, dropped connections, etc.).
+    Transient,
+    /// Retrying will not help.
+    Fatal,
+}
+
+/// Classifies a transport error returned from `eth_getLogs`.
+pub fn classify_log_error(err: &TransportError) -> LogErrorKind {
+    match err {
+        RpcError::ErrorResp(payload) => classify_error_response(payload.code, &payload.message),
+        RpcError::DeserError { text, .. } => classify_error_response(0, text),
+        RpcError::Transport(_) | RpcError::NullResp => LogErrorKind::Transient,
+        _ => LogErrorKind::Fatal,
+    }
+}
+
+/// Classifies a JSON-RPC error response from its code and message.
+///
+/// Providers do not agree on error codes for oversized queries, so the message is inspected as well.
+pub fn classify_error_response(code: i64, message: &str) -> LogErrorKind {
+    let message = message.to_lowercase();
+
+    if code == 429
+        || message.contains("rate limit")
+        || message.contains("too many requests")
+        || message.contains("header not found")
+        || message.contains("try again")
+    {
+        return LogErrorKind::Transient;
+    }
+
+    const RANGE_ERROR_PATTERNS: [&str; 3] = [
+        "block range",
+        "response size exceeded",
+        "query returned more than",
+    ];
+
+    if RANGE_ERROR_PATTERNS
+        .iter()
+        .any(|pattern| message.contains(pattern))
+    {
+        return LogErrorKind::RangeTooLarge {
+            suggested_range: suggested_block_range(&message),
+        };
+    }
+
+    match code {
+        -32005 => LogErrorKind::RangeTooLarge {
+            suggested_range: suggested_block_range(&message),
+        },
+        -32603 | -32000 => LogErrorKind::Transient,
+        _ => LogErrorKind::Fatal,
+    }
+}
+
+/// Extracts a `[from, to]` block range suggested by the provider, if the message contains one.
+fn suggested_block_range(message: &str) -> Option<(u64, u64)> {
+    let mut values = HEX_REGEX
+        .find_iter(message)
+        .filter_map(|m| U256::from_str(m.as_str()).ok())
+        .filter_map(|value| u64::try_from(value).ok());
+
+    let from = values.next()?;
+    let to = values.next()?;
+
+    (from <= to).then_some((from, to))
+}
+
+/// Tracks the block range used for consecutive `eth_getLogs` requests.
+///
+/// The range is halved (or set to the provider's suggestion) whenever a request is rejected as too
+/// large and grows by half again after each successful request, within the configured bounds.
+#[derive(Debug, Clone)]
+pub struct AdaptiveLogRange {
+    step: u64,
+    config: LogRangeConfig,
+}
+
+impl AdaptiveLogRange {
+    pub fn new(config: LogRangeConfig) -> Self {
+        AdaptiveLogRange {
+            step: config.initial_step.clamp(config.min_step, config.max_step),
+            config,
+        }
+    }
+
+    /// Returns the number of blocks to request next.
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    /// Returns whether the range cannot be shrunk any further.
+    pub fn at_min_step(&self) -> bool {
+        self.step <= self.config.min_step
+    }
+
+    pub fn on_success(&mut self) {
+        self.step = self
+            .step
+            .saturating_add(self.step.div_ceil(2))
+            .min(self.config.max_step);
+    }
+
+    pub fn on_range_too_large(&mut self, suggested_range: Option<(u64, u64)>) {
+        let next_step = match suggested_range {
+            Some((from, to)) if to - from < self.step => to - from + 1,
+            _ => self.step / 2,
+        };
+
+        self.step = next_step.clamp(self.config.min_step, self.config.max_step);
+    }
+}
+
+/// Gets all logs matching `filter` from `from_block` to `to_block` (inclusive).
+///
+/// Requests are made sequentially with a block range that adapts to provider limits. Transient
+/// failures are retried with exponential backoff.
+pub async fn get_logs_adaptive<N, P>(
+    filter: &Filter,
+    from_block: u64,
+    to_block: u64,
+    range: &mut AdaptiveLogRange,
+    provider: P,
+) -> Result<Vec<Log>, AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let shared_range = Mutex::new(range.clone());
+    let logs = get_logs_shared(filter, from_block, to_block, &shared_range, provider).await;
+    *range = shared_range.into_inner();
+
+    logs
+}
+
+/// Gets all logs matching `filter` from `from_block` to `to_block` (inclusive), adapting a block
+/// range that may be shared with concurrent requests.
+async fn get_logs_shared<N, P>(
+    filter: &Filter,
+    from_block: u64,
+    to_block: u64,
+    range: &Mutex<AdaptiveLogRange>,
+    provider: P,
+) -> Result<Vec<Log>, AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let config = range.lock().await.config;
+    let mut logs = vec![];
+    let mut from_block = from_block;
+    let mut retries = 0;
+    let mut backoff = config.initial_backoff;
+
+    while from_block <= to_block {
+        let step = range.lock().await.step();
+        let target_block = from_block.saturating_add(step - 1).min(to_block);
+
+        let result = provider
+            .get_logs(&filter.clone().from_block(from_block).to_block(target_block))
+            .await;
+
+        let err = match result {
+            Ok(range_logs) => {
+                events::emit(SyncEvent::BlockRangeSynced {
+                    from_block,
+                    to_block: target_block,
+                    logs: range_logs.len(),
+                });
+
+                logs.extend(range_logs);
+                range.lock().await.on_success();
+                retries = 0;
+                backoff = config.initial_backoff;
+
+                if target_block == u64::MAX {
+                    break;
+                }
+                from_block = target_block + 1;
+                continue;
+            }
+            Err(err) => err,
+        };
+
+        let kind = classify_log_error(&err);
+        if let LogErrorKind::RangeTooLarge { suggested_range } = kind {
+            let mut range = range.lock().await;
+            // A concurrent request may already have shrunk the range below the rejected one
+            let rejected_step = target_block - from_block + 1;
+            if range.step() < rejected_step || !range.at_min_step() {
+                if range.step() >= rejected_step {
+                    range.on_range_too_large(suggested_range);
+                }
+                tracing::debug!(
+                    from_block,
+                    target_block,
+                    step = range.step(),
+                    "log range too large, shrinking"
+                );
+                continue;
+            }
+        }
+
+        match kind {
+            LogErrorKind::RangeTooLarge { .. } | LogErrorKind::Transient
+                if retries < config.max_retries =>
+            {
+                retries += 1;
+                tracing::warn!(
+                    from_block,
+                    target_block,
+                    retries,
+                    ?backoff,
+                    %err,
+                    "error getting logs, retrying"
+                );
+                events::emit(SyncEvent::RequestRetried {
+                    from_block,
+                    to_block: target_block,
+                    attempt: retries,
+                    error: err.to_string(),
+                });
+
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(config.max_backoff);
+            }
+            _ => return Err(AMMError::TransportError(err)),
+        }
+    }
+
+    Ok(logs)
+}
+
+/// Gets all logs matching `filter` from `from_block` to `to_block` (inclusive).
+///
+/// Block ranges are requested concurrently, at most `config.max_concurrent_requests` at a time.
+/// All requests share a single [`AdaptiveLogRange`], each range starts where the previous one
+/// ended and is sized by the step learned so far, so a limit hit by one request applies to every
+/// request that follows. Logs are returned in block order.
+pub async fn get_logs_in_chunks<N, P>(
+    filter: &Filter,
+    from_block: u64,
+    to_block: u64,
+    config: LogRangeConfig,
+    provider: P,
+) -> Result<Vec<Log>, AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let range = Mutex::new(AdaptiveLogRange::new(config));
+    let mut next_block = Some(from_block).filter(|from_block| *from_block <= to_block);
+    let mut requests = FuturesUnordered::new();
+    let mut logs_by_range = BTreeMap::new();
+
+    loop {
+        while requests.len() < config.max_concurrent_requests {
+            let Some(chunk_start) = next_block else {
+                break;
+            };
+
+            let step = range.lock().await.step();
+            let chunk_end = chunk_start.saturating_add(step - 1).min(to_block);
+            next_block = chunk_end.checked_add(1).filter(|block| *block <= to_block);
+
+            let provider = provider.clone();
+            let range = &range;
+            requests.push(async move {
+                let logs = get_logs_shared(filter, chunk_start, chunk_end, range, provider).await;
+                (chunk_start, logs)
+            });
+        }
+
+        let Some((chunk_start, logs)) = requests.next().await else {
+            break;
+        };
+        logs_by_range.insert(chunk_start, logs?);
+    }
+
+    Ok(logs_by_range.into_values().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_error_response() {
+        assert_eq!(
+            classify_error_response(-32005, "query returned more than 10000 results"),
+            LogErrorKind::RangeTooLarge {
+                suggested_range: None
+            }
+        );
+        assert_eq!(
+            classify_error_response(
+                -32602,
+                "Log response size exceeded. You can make eth_getLogs requests with up to a 2K block range and no limit on the response size, or you can request any block range with a cap of 10K logs in the response. Based on your parameters, this block range should work: [0x10, 0x7cf]"
+            ),
+            LogErrorKind::RangeTooLarge {
+                suggested_range: Some((0x10, 0x7cf))
+            }
+        );
+        assert_eq!(
+            classify_error_response(429, "Too Many Requests"),
+            LogErrorKind::Transient
+        );
+        assert_eq!(
+            classify_error_response(-32000, "rate limit exceeded"),
+            LogErrorKind::Transient
+        );
+        assert_eq!(
+            classify_error_response(-32602, "invalid argument 0: hex string without 0x prefix"),
+            LogErrorKind::Fatal
+        );
+        assert_eq!(
+            classify_error_response(-32000, "exceed maximum block range: 50000"),
+            LogErrorKind::RangeTooLarge {
+                suggested_range: None
+            }
+        );
+
+        // Messages only sharing a generic word with range errors keep the class of their code
+        assert_eq!(
+            classify_error_response(-32000, "execution timeout"),
+            LogErrorKind::Transient
+        );
+        assert_eq!(
+            classify_error_response(-32602, "gas limit exceeded"),
+            LogErrorKind::Fatal
+        );
+    }
+
+    #[test]
+    fn test_adaptive_log_range() {
+        let mut range = AdaptiveLogRange::new(LogRangeConfig::new(1000).with_step_bounds(10, 2000));
+
+        range.on_range_too_large(None);
+        assert_eq!(range.step(), 500);
+
+        range.on_range_too_large(Some((100, 199)));
+        assert_eq!(range.step(), 100);
+
+        // A suggestion larger than the current step is ignored
+        range.on_range_too_large(Some((0, 10_000)));
+        assert_eq!(range.step(), 50);
+
+        range.on_success();
+        assert_eq!(range.step(), 75);
+
+        for _ in 0..20 {
+            range.on_success();
+        }
+        assert_eq!(range.step(), 2000);
+
+        for _ in 0..20 {
+            range.on_range_too_large(None);
+        }
+        assert_eq!(range.step(), 10);
+        assert!(range.at_min_step());
+    }
+
+    #[test]
+    fn test_log_range_config_bounds() {
+        let config = LogRangeConfig::new(0);
+        assert_eq!(config.initial_step(), 1);
+
+        // Inverted bounds are normalized instead of panicking when clamping
+        let config = LogRangeConfig::new(1000).with_step_bounds(500, 100);
+        assert_eq!(config.min_step(), 500);
+        assert_eq!(config.max_step(), 500);
+        assert_eq!(config.initial_step(), 500);
+
+        let config = LogRangeConfig::new(1000).with_step_bounds(0, 0);
+        assert_eq!(config.min_step(), 1);
+        assert_eq!(config.initial_step(), 1);
+
+        let mut range = AdaptiveLogRange::new(config);
+        range.on_range_too_large(None);
+        assert_eq!(range.step(), 1);
+    }
+}
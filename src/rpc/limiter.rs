@@ -0,0 +1,294 @@
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use alloy::{
+    rpc::json_rpc::{RequestPacket, ResponsePacket},
+    transports::{TransportError, TransportFut},
+};
+use tokio::{
+    sync::{Mutex, OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+use tower::{Layer, Service};
+
+/// Default maximum number of requests in flight at once.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 32;
+/// Compute units charged for methods without a known cost.
+pub const DEFAULT_COMPUTE_UNITS: u64 = 20;
+
+/// Limits applied to every request passing through a [`RequestLimiter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLimiterConfig {
+    /// Maximum number of requests awaiting a response at any time.
+    pub max_in_flight: usize,
+    /// Maximum number of requests started per second, unlimited if `None`.
+    pub requests_per_second: Option<u64>,
+    /// Maximum compute units spent per second, unlimited if `None`.
+    ///
+    /// See [`method_compute_units`] for the cost of each method.
+    pub compute_units_per_second: Option<u64>,
+}
+
+impl RequestLimiterConfig {
+    pub fn new(
+        max_in_flight: usize,
+        requests_per_second: Option<u64>,
+        compute_units_per_second: Option<u64>,
+    ) -> Self {
+        RequestLimiterConfig {
+            max_in_flight,
+            requests_per_second,
+            compute_units_per_second,
+        }
+    }
+}
+
+impl Default for RequestLimiterConfig {
+    fn default() -> Self {
+        RequestLimiterConfig::new(DEFAULT_MAX_IN_FLIGHT, None, None)
+    }
+}
+
+/// Returns the compute units charged for a JSON-RPC method.
+///
+/// Costs follow the weights used by most hosted providers, where `eth_getLogs` and `eth_call`
+/// (used by all batch requests) are the most expensive calls made by this crate.
+pub fn method_compute_units(method: &str) -> u64 {
+    match method {
+        "eth_chainId" | "net_version" => 0,
+        "eth_blockNumber" => 10,
+        "eth_getBlockByNumber" | "eth_getBlockByHash" => 16,
+        "eth_call" | "eth_estimateGas" => 26,
+        "eth_getLogs" => 75,
+        "eth_subscribe" | "eth_unsubscribe" => 10,
+        _ => DEFAULT_COMPUTE_UNITS,
+    }
+}
+
+/// Returns the compute units charged for a request packet, summing each call in a batch.
+pub fn request_compute_units(request: &RequestPacket) -> u64 {
+    match request {
+        RequestPacket::Single(request) => method_compute_units(request.method()),
+        RequestPacket::Batch(requests) => requests
+            .iter()
+            .map(|request| method_compute_units(request.method()))
+            .sum(),
+    }
+}
+
+#[derive(Debug)]
+struct RateWindow {
+    start: Instant,
+    requests: u64,
+    compute_units: u64,
+}
+
+#[derive(Debug)]
+struct RequestLimiterInner {
+    config: RequestLimiterConfig,
+    in_flight: Arc<Semaphore>,
+    window: Mutex<RateWindow>,
+}
+
+/// Shared concurrency and rate limiter for RPC requests.
+///
+/// Cloning the limiter shares its limits, so a single instance can be used across every provider
+/// talking to the same endpoint. Install it on a client with [`RequestLimiter::layer`] so that all
+/// batch requests, `eth_getLogs` calls and subscriptions made through the provider are limited.
+#[derive(Debug, Clone)]
+pub struct RequestLimiter {
+    inner: Arc<RequestLimiterInner>,
+}
+
+impl RequestLimiter {
+    pub fn new(config: RequestLimiterConfig) -> Self {
+        RequestLimiter {
+            inner: Arc::new(RequestLimiterInner {
+                config,
+                in_flight: Arc::new(Semaphore::new(config.max_in_flight.max(1))),
+                window: Mutex::new(RateWindow {
+                    start: Instant::now(),
+                    requests: 0,
+                    compute_units: 0,
+                }),
+            }),
+        }
+    }
+
+    pub fn config(&self) -> RequestLimiterConfig {
+        self.inner.config
+    }
+
+    /// Returns a layer that routes every request of an RPC client through this limiter.
+    pub fn layer(&self) -> RequestLimiterLayer {
+        RequestLimiterLayer::new(self.clone())
+    }
+
+    /// Waits until a request costing `compute_units` may be sent.
+    ///
+    /// The request counts towards `max_in_flight` until the returned permit is dropped.
+    pub async fn acquire(&self, compute_units: u64) -> OwnedSemaphorePermit {
+        let permit = self
+            .inner
+            .in_flight
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Request limiter semaphore is never closed");
+
+        self.wait_for_budget(compute_units).await;
+
+        permit
+    }
+
+    async fn wait_for_budget(&self, compute_units: u64) {
+        let RequestLimiterConfig {
+            requests_per_second,
+            compute_units_per_second,
+            ..
+        } = self.inner.config;
+
+        if requests_per_second.is_none() && compute_units_per_second.is_none() {
+            return;
+        }
+
+        // The window lock is held while sleeping so that waiters are released in order
+        let mut window = self.inner.window.lock().await;
+        loop {
+            let now = Instant::now();
+            if now.duration_since(window.start) >= Duration::from_secs(1) {
+                window.start = now;
+                window.requests = 0;
+                window.compute_units = 0;
+            }
+
+            let requests_available =
+                requests_per_second.is_none_or(|limit| window.requests < limit);
+            // A request costing more than the whole budget is let through on an empty window
+            let compute_units_available = compute_units_per_second.is_none_or(|limit| {
+                window.compute_units == 0 || window.compute_units + compute_units <= limit
+            });
+
+            if requests_available && compute_units_available {
+                window.requests += 1;
+                window.compute_units += compute_units;
+                return;
+            }
+
+            tokio::time::sleep_until(window.start + Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// Tower layer installing a [`RequestLimiter`] on an RPC client.
+///
+/// ```ignore
+/// let limiter = RequestLimiter::new(RequestLimiterConfig::new(16, Some(25), Some(500)));
+/// let client = ClientBuilder::default().layer(limiter.layer()).http(url);
+/// let provider = ProviderBuilder::new().on_client(client);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequestLimiterLayer {
+    limiter: RequestLimiter,
+}
+
+impl RequestLimiterLayer {
+    pub fn new(limiter: RequestLimiter) -> Self {
+        RequestLimiterLayer { limiter }
+    }
+}
+
+impl<S> Layer<S> for RequestLimiterLayer {
+    type Service = RequestLimiterService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestLimiterService {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+/// Transport service that waits on a [`RequestLimiter`] before forwarding each request.
+#[derive(Debug, Clone)]
+pub struct RequestLimiterService<S> {
+    inner: S,
+    limiter: RequestLimiter,
+}
+
+impl<S> Service<RequestPacket> for RequestLimiterService<S>
+where
+    S: Service<
+            RequestPacket,
+            Response = ResponsePacket,
+            Error = TransportError,
+            Future = TransportFut<'static>,
+        > + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let limiter = self.limiter.clone();
+
+        Box::pin(async move {
+            let _permit = limiter.acquire(request_compute_units(&request)).await;
+            inner.call(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::poll;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_in_flight() {
+        let limiter = RequestLimiter::new(RequestLimiterConfig::new(2, None, None));
+
+        let first = limiter.acquire(1).await;
+        let _second = limiter.acquire(1).await;
+
+        let third = limiter.acquire(1);
+        tokio::pin!(third);
+        assert!(poll!(&mut third).is_pending());
+
+        // Time alone does not release a request slot
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(poll!(&mut third).is_pending());
+
+        drop(first);
+        assert!(poll!(&mut third).is_ready());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_compute_unit_budget() {
+        let limiter = RequestLimiter::new(RequestLimiterConfig::new(10, None, Some(100)));
+
+        let _first = limiter.acquire(75).await;
+
+        // The second request exceeds the remaining budget and waits for the next window
+        let second = limiter.acquire(75);
+        tokio::pin!(second);
+        assert!(poll!(&mut second).is_pending());
+
+        tokio::time::advance(Duration::from_millis(999)).await;
+        assert!(poll!(&mut second).is_pending());
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        assert!(poll!(&mut second).is_ready());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod get_logs;
+pub mod limiter;
+pub mod multicall;
@@ -0,0 +1,84 @@
+use alloy::{
+    eips::BlockId,
+    network::Network,
+    primitives::{address, Address, Bytes},
+    providers::Provider,
+    sol,
+};
+use futures::stream::{self, StreamExt};
+
+use crate::{errors::AMMError, rpc::get_logs::DEFAULT_MAX_CONCURRENT_REQUESTS};
+
+/// Address of the Multicall3 contract, deployed at the same address on most EVM chains.
+pub const MULTICALL3_ADDRESS: Address = address!("cA11bde05977b3631167028862bE2a173976CA11");
+/// Default number of calls aggregated into a single `eth_call`.
+pub const DEFAULT_MULTICALL_BATCH_SIZE: usize = 500;
+
+sol! {
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    contract IMulticall3 {
+        struct Call3 {
+            address target;
+            bool allowFailure;
+            bytes callData;
+        }
+
+        struct Call3Result {
+            bool success;
+            bytes returnData;
+        }
+
+        function aggregate3(Call3[] calldata calls) external payable returns (Call3Result[] memory returnData);
+    }
+}
+
+/// Calls each `(target, call_data)` pair through Multicall3, allowing individual calls to fail.
+///
+/// Calls are sent in batches of [`DEFAULT_MULTICALL_BATCH_SIZE`], at most
+/// [`DEFAULT_MAX_CONCURRENT_REQUESTS`] at a time. Returns the return data of each call in order,
+/// or `None` if the call reverted.
+pub async fn try_aggregate<N, P>(
+    calls: Vec<(Address, Bytes)>,
+    block_number: Option<u64>,
+    provider: P,
+) -> Result<Vec<Option<Bytes>>, AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let block = block_number.map_or(BlockId::latest(), BlockId::from);
+
+    let batches = calls
+        .chunks(DEFAULT_MULTICALL_BATCH_SIZE)
+        .map(|batch| {
+            batch
+                .iter()
+                .map(|(target, call_data)| IMulticall3::Call3 {
+                    target: *target,
+                    allowFailure: true,
+                    callData: call_data.clone(),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = stream::iter(batches)
+        .map(|batch| {
+            let multicall = IMulticall3::new(MULTICALL3_ADDRESS, provider.clone());
+            async move { multicall.aggregate3(batch).block(block).call().await }
+        })
+        .buffered(DEFAULT_MAX_CONCURRENT_REQUESTS);
+
+    let mut return_data = Vec::with_capacity(calls.len());
+    while let Some(result) = results.next().await {
+        return_data.extend(
+            result?
+                .returnData
+                .into_iter()
+                .map(|result| result.success.then_some(result.returnData)),
+        );
+    }
+
+    Ok(return_data)
+}
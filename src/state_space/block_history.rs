@@ -0,0 +1,189 @@
+use std::collections::{HashSet, VecDeque};
+
+use alloy::{
+    network::{BlockResponse, HeaderResponse, Network},
+    primitives::{Address, B256},
+    providers::Provider,
+};
+
+use super::error::StateSpaceError;
+
+/// Ring of the most recently synced block numbers and hashes, used to detect reorgs.
+///
+/// Each block also tracks the AMMs touched since the previous tracked block, so that only those
+/// have to be resynced when a reorg cannot be unwound.
+#[derive(Debug, Clone)]
+pub struct BlockHistory {
+    blocks: VecDeque<(u64, B256)>,
+    touched: VecDeque<HashSet<Address>>,
+    capacity: usize,
+}
+
+impl BlockHistory {
+    pub fn new(capacity: usize) -> Self {
+        BlockHistory {
+            blocks: VecDeque::with_capacity(capacity),
+            touched: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Returns the most recently synced block.
+    pub fn latest(&self) -> Option<(u64, B256)> {
+        self.blocks.back().copied()
+    }
+
+    /// Returns the oldest block still tracked.
+    pub fn oldest(&self) -> Option<(u64, B256)> {
+        self.blocks.front().copied()
+    }
+
+    /// Returns the hash of the synced block at `block_number`, if tracked.
+    pub fn hash_at(&self, block_number: u64) -> Option<B256> {
+        self.blocks
+            .iter()
+            .rev()
+            .find(|(number, _)| *number == block_number)
+            .map(|(_, hash)| *hash)
+    }
+
+    /// Records a synced block, replacing any tracked blocks at or above its number.
+    pub fn push(&mut self, block_number: u64, block_hash: B256) {
+        self.push_with_touched(block_number, block_hash, HashSet::new());
+    }
+
+    /// Records a synced block and the AMMs touched since the previous tracked block, replacing
+    /// any tracked blocks at or above its number.
+    pub fn push_with_touched(
+        &mut self,
+        block_number: u64,
+        block_hash: B256,
+        touched: HashSet<Address>,
+    ) {
+        while self
+            .blocks
+            .back()
+            .is_some_and(|(number, _)| *number >= block_number)
+        {
+            self.blocks.pop_back();
+            self.touched.pop_back();
+        }
+
+        if self.blocks.len() == self.capacity {
+            self.blocks.pop_front();
+            self.touched.pop_front();
+        }
+        self.blocks.push_back((block_number, block_hash));
+        self.touched.push_back(touched);
+    }
+
+    /// Removes all tracked blocks above `block_number`.
+    pub fn truncate_after(&mut self, block_number: u64) {
+        while self
+            .blocks
+            .back()
+            .is_some_and(|(number, _)| *number > block_number)
+        {
+            self.blocks.pop_back();
+            self.touched.pop_back();
+        }
+    }
+
+    /// Returns the AMMs touched by the tracked blocks above `block_number`.
+    pub fn touched_after(&self, block_number: u64) -> HashSet<Address> {
+        self.blocks
+            .iter()
+            .zip(self.touched.iter())
+            .filter(|((number, _), _)| *number > block_number)
+            .flat_map(|(_, touched)| touched.iter().copied())
+            .collect()
+    }
+}
+
+/// Walks back from `from_block` and returns the newest tracked block that is still canonical.
+///
+/// Returns `None` if none of the tracked blocks at or below `from_block` are canonical.
+pub async fn find_common_ancestor<N, P>(
+    history: &BlockHistory,
+    provider: &P,
+    from_block: u64,
+) -> Result<Option<u64>, StateSpaceError<N>>
+where
+    N: Network,
+    P: Provider<N>,
+{
+    for &(block_number, block_hash) in history.blocks.iter().rev() {
+        if block_number > from_block {
+            continue;
+        }
+
+        let canonical_hash = provider
+            .get_block_by_number(block_number.into())
+            .await?
+            .map(|block| block.header().hash());
+
+        if canonical_hash == Some(block_hash) {
+            return Ok(Some(block_number));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_history() {
+        let mut history = BlockHistory::new(3);
+        assert!(history.is_empty());
+
+        for block_number in 1..=4 {
+            history.push(block_number, B256::with_last_byte(block_number as u8));
+        }
+
+        // Only the last 3 blocks are tracked
+        assert_eq!(history.oldest(), Some((2, B256::with_last_byte(2))));
+        assert_eq!(history.latest(), Some((4, B256::with_last_byte(4))));
+        assert_eq!(history.hash_at(1), None);
+        assert_eq!(history.hash_at(3), Some(B256::with_last_byte(3)));
+
+        // A block at an already tracked height replaces it and every block above it
+        history.push(3, B256::with_last_byte(0x33));
+        assert_eq!(history.latest(), Some((3, B256::with_last_byte(0x33))));
+        assert_eq!(history.hash_at(4), None);
+
+        history.truncate_after(2);
+        assert_eq!(history.latest(), Some((2, B256::with_last_byte(2))));
+    }
+
+    #[test]
+    fn test_touched_after() {
+        let mut history = BlockHistory::new(3);
+        for block_number in 1..=4 {
+            history.push_with_touched(
+                block_number,
+                B256::with_last_byte(block_number as u8),
+                HashSet::from([Address::with_last_byte(block_number as u8)]),
+            );
+        }
+
+        assert_eq!(
+            history.touched_after(2),
+            HashSet::from([Address::with_last_byte(3), Address::with_last_byte(4)])
+        );
+
+        // Replaced blocks no longer count as touched
+        history.push(4, B256::with_last_byte(0x44));
+        assert_eq!(
+            history.touched_after(2),
+            HashSet::from([Address::with_last_byte(3)])
+        );
+        assert!(history.touched_after(4).is_empty());
+    }
+}
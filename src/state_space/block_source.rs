@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use alloy::{
+    network::{BlockResponse, Network},
+    providers::Provider,
+};
+use futures::StreamExt;
+use tokio::{sync::mpsc::Sender, time::MissedTickBehavior};
+
+use super::error::{BlockSendErrorWrapper, StateSpaceError};
+
+/// Default interval between polls for a new block.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Where the [`StateSpaceManager`](super::StateSpaceManager) receives new blocks from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlockSource {
+    /// Subscribe to new block headers, requires a pubsub (WebSocket or IPC) provider.
+    #[default]
+    Subscription,
+    /// Poll for the latest block every `interval`, for HTTP-only providers.
+    ///
+    /// Blocks are only emitted once they have `confirmations` blocks built on top of them.
+    Polling {
+        interval: Duration,
+        confirmations: u64,
+    },
+}
+
+impl BlockSource {
+    pub fn polling(interval: Duration, confirmations: u64) -> Self {
+        BlockSource::Polling {
+            interval,
+            confirmations,
+        }
+    }
+}
+
+/// Sends every new block header from the provider's block subscription to `block_tx`.
+pub(crate) async fn subscribe_blocks<N, P>(
+    provider: P,
+    block_tx: Sender<N::HeaderResponse>,
+) -> Result<(), StateSpaceError<N>>
+where
+    N: Network,
+    P: Provider<N>,
+{
+    let subscription = provider.subscribe_blocks().await?;
+    let mut block_stream = subscription.into_stream();
+    while let Some(block) = block_stream.next().await {
+        block_tx
+            .send(block)
+            .await
+            .map_err(BlockSendErrorWrapper::<N>)?;
+    }
+
+    Ok(())
+}
+
+/// Polls the provider for new blocks and sends each confirmed block header to `block_tx` in order.
+///
+/// Failed polls are logged and retried on the next interval.
+pub(crate) async fn poll_blocks<N, P>(
+    provider: P,
+    block_tx: Sender<N::HeaderResponse>,
+    interval: Duration,
+    confirmations: u64,
+) -> Result<(), StateSpaceError<N>>
+where
+    N: Network,
+    P: Provider<N>,
+{
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // The next block to send, starting from the first confirmed block seen
+    let mut next_block: Option<u64> = None;
+
+    loop {
+        interval.tick().await;
+
+        let confirmed_block = match provider.get_block_number().await {
+            Ok(block_number) => block_number.saturating_sub(confirmations),
+            Err(err) => {
+                tracing::warn!(?err, "failed to poll block number");
+                continue;
+            }
+        };
+
+        let mut block_number = next_block.unwrap_or(confirmed_block);
+        while block_number <= confirmed_block {
+            let header = match provider.get_block_by_number(block_number.into()).await {
+                Ok(Some(block)) => block.header().clone(),
+                // The node serving the request may lag behind, retry on the next interval
+                Ok(None) => break,
+                Err(err) => {
+                    tracing::warn!(block_number, ?err, "failed to poll block");
+                    break;
+                }
+            };
+
+            block_tx
+                .send(header)
+                .await
+                .map_err(BlockSendErrorWrapper::<N>)?;
+
+            block_number += 1;
+            next_block = Some(block_number);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use crate::amm::{AutomatedMarketMaker, AMM};
 
-use super::StateChange;
+use super::{error::StateChangeCacheError, StateChange};
 use arraydeque::{ArrayDeque, CapacityError};
 
 #[derive(Debug)]
@@ -44,13 +44,28 @@ impl<const CAP: usize> StateChangeCache<CAP> {
         cache.push_front(state_change)
     }
 
+    /// Removes all state changes from the cache
+    pub fn clear(&mut self) {
+        self.oldest_block = 0;
+        self.cache.clear();
+    }
+
     /// Unwinds the state changes up to the given block number
     /// Returns the state of the affected AMMs at the block number provided
-    pub fn unwind_state_changes(&mut self, block_to_unwind: u64) -> Vec<AMM> {
+    ///
+    /// Returns an error without modifying the cache if `block_to_unwind` is older than the oldest
+    /// block in the cache, in which case the affected AMMs have to be resynced.
+    pub fn unwind_state_changes(
+        &mut self,
+        block_to_unwind: u64,
+    ) -> Result<Vec<AMM>, StateChangeCacheError> {
         let cache = &mut self.cache;
 
         if block_to_unwind < self.oldest_block {
-            panic!("Block to unwind < oldest block in cache");
+            return Err(StateChangeCacheError::ReorgExceedsCache {
+                block_to_unwind,
+                oldest_block: self.oldest_block,
+            });
         }
 
         // If the block to unwind is greater than the latest state change in the block, exit early
@@ -58,7 +73,7 @@ impl<const CAP: usize> StateChangeCache<CAP> {
             .front()
             .is_none_or(|latest| block_to_unwind > latest.block_number)
         {
-            return vec![];
+            return Ok(vec![]);
         }
 
         let pivot_idx = cache
@@ -71,7 +86,7 @@ impl<const CAP: usize> StateChangeCache<CAP> {
             cache.drain(..).collect::<Vec<StateChange>>()
         };
 
-        self.flatten_state_changes(state_changes)
+        Ok(self.flatten_state_changes(state_changes))
     }
 
     fn flatten_state_changes(&self, state_changes: Vec<StateChange>) -> Vec<AMM> {
@@ -89,4 +104,55 @@ impl<const CAP: usize> StateChangeCache<CAP> {
     }
 }
 
-// TODO: add tests
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::Address;
+
+    use super::*;
+    use crate::amm::uniswap_v2::UniswapV2Pool;
+
+    fn pool_at_block(block_number: u64) -> AMM {
+        AMM::UniswapV2Pool(UniswapV2Pool {
+            address: Address::with_last_byte(1),
+            reserve_0: block_number as u128,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_unwind_state_changes() {
+        let mut cache = StateChangeCache::<3>::new();
+        for block_number in 1..=5 {
+            cache
+                .add_state_change_to_cache(StateChange::new(
+                    vec![pool_at_block(block_number)],
+                    block_number,
+                ))
+                .unwrap();
+        }
+
+        // Blocks 1 and 2 have been evicted
+        assert!(matches!(
+            cache.unwind_state_changes(2),
+            Err(StateChangeCacheError::ReorgExceedsCache {
+                block_to_unwind: 2,
+                oldest_block: 3
+            })
+        ));
+
+        // Nothing to unwind past the latest state change
+        assert!(cache.unwind_state_changes(6).unwrap().is_empty());
+
+        let amms = cache.unwind_state_changes(4).unwrap();
+        assert_eq!(amms.len(), 1);
+        let AMM::UniswapV2Pool(pool) = &amms[0] else {
+            panic!("Unexpected AMM variant");
+        };
+        // The state recorded before block 4 was applied
+        assert_eq!(pool.reserve_0, 4);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert!(cache.unwind_state_changes(0).unwrap().is_empty());
+    }
+}
@@ -2,16 +2,20 @@ use std::fmt;
 
 use crate::errors::{AMMError, ArithmeticError, EventLogError};
 
-use alloy::{network::Network, primitives::Address, transports::TransportError};
+use alloy::{
+    network::Network,
+    primitives::{Address, FixedBytes, B256, U256},
+    transports::TransportError,
+};
 
 use arraydeque::CapacityError;
 use thiserror::Error;
 
-use super::StateChange;
+use super::{BlockStateUpdate, StateChange};
 
 // Define newtype wrappers to distinguish between the SendErrors
 #[derive(Debug)]
-pub struct StateChangeSendErrorWrapper(pub tokio::sync::mpsc::error::SendError<Vec<Address>>);
+pub struct StateChangeSendErrorWrapper(pub tokio::sync::mpsc::error::SendError<BlockStateUpdate>);
 
 #[derive(Debug)]
 pub struct BlockSendErrorWrapper<N: Network>(
@@ -68,4 +72,85 @@ pub enum StateSpaceError<N: Network> {
     AlreadyListeningForStateChanges,
     #[error(transparent)]
     JoinError(#[from] tokio::task::JoinError),
+    #[error(transparent)]
+    StateChangeCacheError(#[from] StateChangeCacheError),
+    #[error("Parent {parent_hash} of replayed block {block_number} has not been replayed")]
+    ReplayParentNotFound {
+        block_number: u64,
+        parent_hash: B256,
+    },
+    #[error("Replayed block {block_number} does not follow the synced block, expected {expected}")]
+    ReplayGap { expected: u64, block_number: u64 },
+    #[error("Reorg depth {depth} is not between 1 and the state change cache capacity {capacity}")]
+    InvalidReorgDepth { depth: usize, capacity: usize },
+}
+
+#[derive(Error, Debug)]
+pub enum MultiChainError<N: Network> {
+    #[error("Invalid configuration of chain {chain_id}: {source}")]
+    ChainConfigError {
+        chain_id: u64,
+        #[source]
+        source: StateSpaceError<N>,
+    },
+    #[error("Failed to start the state space of chain {chain_id}: {source}")]
+    ChainStartError {
+        chain_id: u64,
+        #[source]
+        source: StateSpaceError<N>,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum StateChangeCacheError {
+    #[error(
+        "Block to unwind {block_to_unwind} is older than the oldest block in cache {oldest_block}"
+    )]
+    ReorgExceedsCache {
+        block_to_unwind: u64,
+        oldest_block: u64,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum PendingTxError {
+    #[error(transparent)]
+    AMMError(#[from] AMMError),
+    #[error(transparent)]
+    EthABIError(#[from] alloy::sol_types::Error),
+    #[error("Transaction target {0} is neither a known router nor an AMM in the state space")]
+    UnknownTarget(Address),
+    #[error("Unsupported call with selector {0}")]
+    UnsupportedCall(FixedBytes<4>),
+    #[error("Exact output swaps are not supported for {0}")]
+    UnsupportedExactOutput(Address),
+    #[error("Invalid swap path")]
+    InvalidPath,
+    #[error("No pool found for {token_in} -> {token_out}")]
+    PoolNotFound {
+        token_in: Address,
+        token_out: Address,
+    },
+    #[error("Insufficient liquidity in {0}")]
+    InsufficientLiquidity(Address),
+    #[error("Swap overflows the reserves of {0}")]
+    ReserveOverflow(Address),
+    #[error("Swap output {amount_out} is below the minimum {amount_out_min}")]
+    InsufficientOutputAmount {
+        amount_out: U256,
+        amount_out_min: U256,
+    },
+    #[error("Swap input {amount_in} is above the maximum {amount_in_max}")]
+    ExcessiveInputAmount {
+        amount_in: U256,
+        amount_in_max: U256,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
 }
@@ -1,40 +1,72 @@
+pub mod block_history;
+pub mod block_source;
 pub mod cache;
 pub mod error;
+pub mod multi_chain;
+pub mod pending;
+pub mod replay;
+pub mod store;
 
 use crate::{
-    amm::{AutomatedMarketMaker, AMM},
+    amm::{
+        factory::{AutomatedMarketMakerFactory, Factory},
+        AutomatedMarketMaker, AMM,
+    },
     errors::EventLogError,
+    rpc::get_logs::DEFAULT_MAX_CONCURRENT_REQUESTS,
+    sync::populate_amms,
 };
 use alloy::{
     consensus::BlockHeader,
-    network::Network,
-    primitives::{Address, FixedBytes},
+    network::{HeaderResponse, Network},
+    primitives::{Address, B256},
     providers::Provider,
     rpc::types::eth::{Filter, Log},
 };
+use block_history::BlockHistory;
+use block_source::BlockSource;
 use cache::StateChangeCache;
-use error::StateSpaceError;
-use futures::StreamExt;
+use error::{StateChangeCacheError, StateSpaceError};
+use futures::{stream, StreamExt};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashSet,
     marker::PhantomData,
     ops::{Deref, DerefMut},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
+use store::{StateSpaceBackend, StateSpaceStore};
 use tokio::{
-    sync::{mpsc::Receiver, RwLock},
+    sync::{broadcast, mpsc::Receiver, watch, RwLock},
     task::JoinHandle,
 };
 
-use self::error::{BlockSendErrorWrapper, StateChangeSendErrorWrapper};
+use self::error::StateChangeSendErrorWrapper;
 
-// TODO: bench this with a dashmap
-#[derive(Debug)]
-pub struct StateSpace(pub HashMap<Address, AMM>);
+/// Default number of updates buffered for each subscriber before it starts lagging.
+pub const DEFAULT_BROADCAST_CAPACITY: usize = 256;
+
+/// AMMs keyed by address, stored in a persistent map so that clones are cheap and share
+/// unchanged AMMs with the original.
+#[derive(Debug, Clone)]
+pub struct StateSpace(pub imbl::HashMap<Address, AMM>);
 
 impl StateSpace {
     pub fn new() -> Self {
-        StateSpace(HashMap::new())
+        StateSpace(imbl::HashMap::new())
+    }
+
+    /// Applies the updated and added AMMs of `update`, sharing every other AMM with the
+    /// previous state.
+    pub fn apply_update(&mut self, update: &BlockStateUpdate) {
+        for (address, _, amm) in update.updated.iter() {
+            self.0.insert(*address, amm.clone());
+        }
+        for amm in update.added.iter() {
+            self.0.insert(amm.address(), amm.clone());
+        }
     }
 }
 
@@ -45,7 +77,7 @@ impl Default for StateSpace {
 }
 
 impl Deref for StateSpace {
-    type Target = HashMap<Address, AMM>;
+    type Target = imbl::HashMap<Address, AMM>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -65,10 +97,40 @@ impl From<Vec<AMM>> for StateSpace {
     }
 }
 
+/// Immutable view of the state space after all state changes up to `block_number` were applied.
+///
+/// Snapshots are cheap to clone and are not affected by later state changes, so they can be
+/// held for as long as needed without blocking the [`StateSpaceManager`].
+#[derive(Debug, Clone, Default)]
+pub struct StateSnapshot {
+    pub block_number: u64,
+    pub amms: StateSpace,
+}
+
+impl Deref for StateSnapshot {
+    type Target = StateSpace;
+
+    fn deref(&self) -> &Self::Target {
+        &self.amms
+    }
+}
+
 #[derive(Debug)]
 pub struct StateSpaceManager<N, P, const CAP: usize> {
-    state: Arc<RwLock<StateSpace>>,
+    state: StateSpaceStore,
     state_change_cache: Arc<RwLock<StateChangeCache<CAP>>>,
+    /// Factories whose creation events add new AMMs to the state space
+    factories: Vec<Factory>,
+    block_source: BlockSource,
+    /// Number of recent blocks tracked to find the common ancestor of a reorg, at most `CAP`
+    reorg_depth: usize,
+    /// Number of blocks between refreshes of the ERC4626 vault share rates
+    rate_refresh_interval: Option<u64>,
+    /// Fans out state updates to every subscriber once the manager is started
+    updates_tx: broadcast::Sender<Arc<BlockStateUpdate>>,
+    listening: Arc<AtomicBool>,
+    /// Latest state, published once each block is fully synced
+    snapshot_tx: Arc<watch::Sender<StateSnapshot>>,
     provider: P,
     phantom: PhantomData<N>,
 }
@@ -79,34 +141,114 @@ where
     P: Provider<N> + Clone + 'static,
 {
     pub fn new(amms: Vec<AMM>, provider: P) -> Self {
+        let state: StateSpace = amms.into();
+        let snapshot = StateSnapshot {
+            block_number: 0,
+            amms: state.clone(),
+        };
+
         Self {
-            state: Arc::new(RwLock::new(amms.into())),
+            state: StateSpaceStore::new(StateSpaceBackend::default(), state),
             state_change_cache: Arc::new(RwLock::new(StateChangeCache::new())),
+            factories: vec![],
+            block_source: BlockSource::default(),
+            reorg_depth: 30,
+            rate_refresh_interval: None,
+            updates_tx: broadcast::channel(DEFAULT_BROADCAST_CAPACITY).0,
+            listening: Arc::new(AtomicBool::new(false)),
+            snapshot_tx: Arc::new(watch::channel(snapshot).0),
             provider,
             phantom: PhantomData,
         }
     }
+}
 
+impl<N, P, const CAP: usize> StateSpaceManager<N, P, CAP>
+where
+    N: Network,
+    P: Provider<N> + Clone + 'static,
+{
     pub async fn filter(&self) -> Filter {
-        let event_signatures = self
-            .state
-            .read()
+        build_filter(&self.state, &self.factories).await
+    }
+
+    /// Returns the state space shared with the tasks syncing it.
+    pub fn state(&self) -> StateSpaceStore {
+        self.state.clone()
+    }
+
+    /// Returns a snapshot of the state space at the latest fully synced block.
+    ///
+    /// Until the manager starts listening for state changes, the snapshot holds the initial AMMs
+    /// at block 0.
+    pub fn snapshot(&self) -> StateSnapshot {
+        self.snapshot_tx.borrow().clone()
+    }
+
+    /// Returns a receiver notified with a new [`StateSnapshot`] after each synced block.
+    pub fn subscribe_snapshots(&self) -> watch::Receiver<StateSnapshot> {
+        self.snapshot_tx.subscribe()
+    }
+
+    /// Returns a new receiver for the [`BlockStateUpdate`]s sent once the manager is [started](Self::start).
+    ///
+    /// Any number of receivers can be created, each receiving every update sent after it was
+    /// created. A receiver that falls more than the broadcast capacity behind skips the oldest
+    /// updates and gets [`broadcast::error::RecvError::Lagged`], after which it should re-read
+    /// the [state](Self::state) rather than rely on the updates it missed.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<BlockStateUpdate>> {
+        self.updates_tx.subscribe()
+    }
+
+    /// Listens to new blocks and handles state changes, broadcasting a [`BlockStateUpdate`] to
+    /// every receiver returned by [`subscribe`](Self::subscribe).
+    ///
+    /// Slow receivers never hold back the manager. Returns
+    /// [`StateSpaceError::AlreadyListeningForStateChanges`] if the manager is already started.
+    pub async fn start(
+        &self,
+        latest_synced_block: u64,
+        buffer: usize,
+    ) -> Result<Vec<JoinHandle<Result<(), StateSpaceError<N>>>>, StateSpaceError<N>> {
+        if self.listening.swap(true, Ordering::AcqRel) {
+            return Err(StateSpaceError::AlreadyListeningForStateChanges);
+        }
+
+        let (mut updates_rx, mut handles) = match self
+            .subscribe_state_changes(latest_synced_block, buffer)
             .await
-            .values()
-            .flat_map(|amm| amm.sync_on_event_signatures())
-            .collect::<Vec<FixedBytes<32>>>();
+        {
+            Ok(subscription) => subscription,
+            Err(err) => {
+                self.listening.store(false, Ordering::Release);
+                return Err(err);
+            }
+        };
+
+        let updates_tx = self.updates_tx.clone();
+        let listening = self.listening.clone();
+        handles.push(tokio::spawn(async move {
+            while let Some(update) = updates_rx.recv().await {
+                // Sending only fails when there are no receivers, in which case the update is dropped
+                let _ = updates_tx.send(Arc::new(update));
+            }
 
-        Filter::new().event_signature(event_signatures)
+            // The sync task stopped, allow the manager to be started again
+            listening.store(false, Ordering::Release);
+            Ok(())
+        }));
+
+        Ok(handles)
     }
 
-    /// Listens to new blocks and handles state changes, sending a Vec<H160> containing each AMM address that incurred a state change in the block.
+    /// Listens to new blocks and handles state changes, sending a [`BlockStateUpdate`] for each block that changed the state of an AMM.
     pub async fn subscribe_state_changes(
         &self,
         latest_synced_block: u64,
         buffer: usize,
     ) -> Result<
         (
-            Receiver<Vec<Address>>,
+            Receiver<BlockStateUpdate>,
             Vec<JoinHandle<Result<(), StateSpaceError<N>>>>,
         ),
         StateSpaceError<N>,
@@ -130,18 +272,20 @@ where
         let (stream_tx, stream_rx) = tokio::sync::mpsc::channel(buffer);
 
         let provider = self.provider.clone();
-        let stream_handle = tokio::spawn(async move {
-            let subscription = provider.subscribe_blocks().await?;
-            let mut block_stream = subscription.into_stream();
-            while let Some(block) = block_stream.next().await {
-                stream_tx
-                    .send(block)
-                    .await
-                    .map_err(BlockSendErrorWrapper::<N>)?;
+        let stream_handle = match self.block_source {
+            BlockSource::Subscription => {
+                tokio::spawn(block_source::subscribe_blocks(provider, stream_tx))
             }
-
-            Ok::<(), StateSpaceError<N>>(())
-        });
+            BlockSource::Polling {
+                interval,
+                confirmations,
+            } => tokio::spawn(block_source::poll_blocks(
+                provider,
+                stream_tx,
+                interval,
+                confirmations,
+            )),
+        };
 
         (stream_rx, stream_handle)
     }
@@ -152,35 +296,136 @@ where
         mut stream_rx: Receiver<<N as alloy::providers::Network>::HeaderResponse>,
         buffer: usize,
     ) -> (
-        Receiver<Vec<Address>>,
+        Receiver<BlockStateUpdate>,
         JoinHandle<Result<(), StateSpaceError<N>>>,
     ) {
         let state = self.state.clone();
         let provider = self.provider.clone();
-        let filter = self.filter().await;
+        let mut filter = self.filter().await;
         let state_change_cache = self.state_change_cache.clone();
+        let factories = self.factories.clone();
+        let snapshot_tx = self.snapshot_tx.clone();
+        let rate_refresh_interval = self.rate_refresh_interval;
+        let reorg_depth = self.reorg_depth;
 
         let (amms_updated_tx, amms_updated_rx) = tokio::sync::mpsc::channel(buffer);
 
         let updated_amms_handle: JoinHandle<Result<(), StateSpaceError<N>>> =
             tokio::spawn(async move {
+                let mut block_history = BlockHistory::new(reorg_depth);
+                // Kept up to date from the block updates so that publishing a snapshot does not
+                // copy the whole state space
+                let mut snapshot_amms = state.snapshot().await;
+                // The vaults are synced at the latest synced block
+                let mut last_rate_refresh = latest_synced_block;
+
+                snapshot_tx.send_replace(StateSnapshot {
+                    block_number: latest_synced_block,
+                    amms: snapshot_amms.clone(),
+                });
+
                 while let Some(block) = stream_rx.recv().await {
                     let chain_head_block_number = block.number();
+                    let chain_head_block_hash = block.hash();
 
-                    // If the chain head block number <= latest synced block, a reorg has occurred
-                    if chain_head_block_number <= latest_synced_block {
-                        tracing::trace!(
-                            chain_head_block_number,
-                            latest_synced_block,
-                            "reorg detected, unwinding state changes"
-                        );
+                    // Skip blocks that have already been synced
+                    if block_history.hash_at(chain_head_block_number) == Some(chain_head_block_hash)
+                    {
+                        continue;
+                    }
 
-                        latest_synced_block = unwind_state_changes(
-                            state.clone(),
-                            state_change_cache.clone(),
-                            chain_head_block_number,
+                    // Find the newest synced block that the new block builds on
+                    let parent_block_number = chain_head_block_number.saturating_sub(1);
+                    let common_ancestor = if block_history.hash_at(parent_block_number)
+                        == Some(block.parent_hash())
+                    {
+                        Some(parent_block_number)
+                    } else if block_history.is_empty() {
+                        Some(latest_synced_block)
+                    } else {
+                        block_history::find_common_ancestor(
+                            &block_history,
+                            &provider,
+                            parent_block_number,
                         )
-                        .await;
+                        .await?
+                    };
+
+                    // If the new block does not build on the latest synced block, a reorg has occurred
+                    let reorg_update = match common_ancestor {
+                        Some(common_ancestor) if common_ancestor >= latest_synced_block => None,
+                        Some(common_ancestor) => {
+                            tracing::warn!(
+                                chain_head_block_number,
+                                latest_synced_block,
+                                common_ancestor,
+                                "reorg detected, unwinding state changes"
+                            );
+
+                            let updated = match unwind_state_changes(
+                                state.clone(),
+                                state_change_cache.clone(),
+                                common_ancestor + 1,
+                            )
+                            .await
+                            {
+                                Ok((_, unwound_amms)) => unwound_amms,
+                                Err(err) => {
+                                    tracing::warn!(
+                                        %err,
+                                        "reorg exceeds state change cache, resyncing state space"
+                                    );
+                                    resync_state_space(
+                                        state.clone(),
+                                        state_change_cache.clone(),
+                                        Some(block_history.touched_after(common_ancestor)),
+                                        common_ancestor,
+                                        provider.clone(),
+                                    )
+                                    .await?
+                                }
+                            };
+
+                            Some((common_ancestor, updated))
+                        }
+                        None => {
+                            // None of the tracked blocks are canonical, resync from the new parent
+                            tracing::warn!(
+                                chain_head_block_number,
+                                latest_synced_block,
+                                "reorg deeper than block history, resyncing state space"
+                            );
+                            let updated = resync_state_space(
+                                state.clone(),
+                                state_change_cache.clone(),
+                                None,
+                                parent_block_number,
+                                provider.clone(),
+                            )
+                            .await?;
+
+                            Some((parent_block_number, updated))
+                        }
+                    };
+
+                    let reorged = reorg_update.is_some();
+                    let mut block_updates = vec![];
+                    if let Some((common_ancestor, updated)) = reorg_update {
+                        let block_hash = if common_ancestor == parent_block_number {
+                            block.parent_hash()
+                        } else {
+                            block_history.hash_at(common_ancestor).unwrap_or_default()
+                        };
+
+                        latest_synced_block = common_ancestor;
+                        block_history.truncate_after(common_ancestor);
+                        // Refreshes after the common ancestor were unwound
+                        last_rate_refresh = last_rate_refresh.min(common_ancestor);
+
+                        let mut update = BlockStateUpdate::new(common_ancestor, block_hash);
+                        update.updated = updated;
+                        update.reorged = true;
+                        block_updates.push(update);
                     }
 
                     // Get logs from the provider that match the event signatures from the state space
@@ -194,22 +439,114 @@ where
                         .await?;
 
                     // Handle any state changes from the logs
-                    if !logs.is_empty() {
-                        let amms_updated = handle_state_changes_from_logs(
+                    if let Some(last_log) = logs.last() {
+                        let last_log_block_number = get_block_number_from_log(last_log)?;
+                        let last_log_block_hash = last_log.block_hash.unwrap_or_default();
+
+                        // Add AMMs created by the factories before syncing the existing AMMs
+                        let (logs, new_amms) = if factories.is_empty() {
+                            (logs, vec![])
+                        } else {
+                            add_amms_from_logs(state.clone(), &factories, logs, provider.clone())
+                                .await?
+                        };
+
+                        // Listen for the events of any newly added AMM variants
+                        if !new_amms.is_empty() {
+                            filter = build_filter(&state, &factories).await;
+                        }
+
+                        let mut updates = handle_block_state_changes_from_logs(
                             state.clone(),
                             state_change_cache.clone(),
                             logs,
                         )
                         .await?;
 
-                        amms_updated_tx
-                            .send(amms_updated)
-                            .await
-                            .map_err(StateChangeSendErrorWrapper)?;
+                        // New AMMs are populated at the block of the last log
+                        if !new_amms.is_empty() {
+                            if updates
+                                .last()
+                                .is_none_or(|update| update.block_number != last_log_block_number)
+                            {
+                                updates.push(BlockStateUpdate::new(
+                                    last_log_block_number,
+                                    last_log_block_hash,
+                                ));
+                            }
+
+                            if let Some(update) = updates.last_mut() {
+                                for address in new_amms.iter() {
+                                    update.added.extend(state.get(address).await);
+                                }
+                            }
+                        }
+
+                        for mut update in updates {
+                            update.reorged = reorged;
+                            block_updates.push(update);
+                        }
+                    }
+
+                    // Vault rates change without emitting logs, refresh them periodically
+                    if rate_refresh_interval.is_some_and(|interval| {
+                        chain_head_block_number.saturating_sub(last_rate_refresh) >= interval.max(1)
+                    }) {
+                        last_rate_refresh = chain_head_block_number;
+
+                        let refreshed_vaults = refresh_vault_rates(
+                            state.clone(),
+                            state_change_cache.clone(),
+                            chain_head_block_number,
+                            provider.clone(),
+                        )
+                        .await;
+
+                        if !refreshed_vaults.is_empty() {
+                            if block_updates
+                                .last()
+                                .is_none_or(|update| update.block_number != chain_head_block_number)
+                            {
+                                let mut update = BlockStateUpdate::new(
+                                    chain_head_block_number,
+                                    chain_head_block_hash,
+                                );
+                                update.reorged = reorged;
+                                block_updates.push(update);
+                            }
+
+                            if let Some(update) = block_updates.last_mut() {
+                                update.updated.extend(refreshed_vaults);
+                            }
+                        }
                     }
 
                     // Once all amms are synced, update the latest synced block
                     latest_synced_block = chain_head_block_number;
+                    block_history.push_with_touched(
+                        chain_head_block_number,
+                        chain_head_block_hash,
+                        block_updates
+                            .iter()
+                            .flat_map(|update| update.addresses())
+                            .collect(),
+                    );
+
+                    // Publish the snapshot before the updates so that subscribers see the new state
+                    for update in block_updates.iter() {
+                        snapshot_amms.apply_update(update);
+                    }
+                    snapshot_tx.send_replace(StateSnapshot {
+                        block_number: latest_synced_block,
+                        amms: snapshot_amms.clone(),
+                    });
+
+                    for update in block_updates {
+                        amms_updated_tx
+                            .send(update)
+                            .await
+                            .map_err(StateChangeSendErrorWrapper)?;
+                    }
                 }
 
                 Ok::<(), StateSpaceError<N>>(())
@@ -217,21 +554,87 @@ where
 
         (amms_updated_rx, updated_amms_handle)
     }
-}
 
-impl<N, P, const CAP: usize> StateSpaceManager<N, P, CAP>
-where
-    N: Network,
-    P: Provider<N> + Clone + 'static,
-{
     pub fn new_with_capacity(amms: Vec<AMM>, provider: P) -> Self {
+        let state: StateSpace = amms.into();
+        let snapshot = StateSnapshot {
+            block_number: 0,
+            amms: state.clone(),
+        };
+
         Self {
-            state: Arc::new(RwLock::new(amms.into())),
+            state: StateSpaceStore::new(StateSpaceBackend::default(), state),
             state_change_cache: Arc::new(RwLock::new(StateChangeCache::new())),
+            factories: vec![],
+            block_source: BlockSource::default(),
+            reorg_depth: CAP,
+            rate_refresh_interval: None,
+            updates_tx: broadcast::channel(DEFAULT_BROADCAST_CAPACITY).0,
+            listening: Arc::new(AtomicBool::new(false)),
+            snapshot_tx: Arc::new(watch::channel(snapshot).0),
             provider,
             phantom: PhantomData,
         }
     }
+
+    /// Adds AMMs created by `factories` to the state space as their creation events are observed.
+    pub fn with_factories(mut self, factories: Vec<Factory>) -> Self {
+        self.factories = factories;
+        self
+    }
+
+    /// Sets where new blocks are received from, defaults to [`BlockSource::Subscription`].
+    pub fn with_block_source(mut self, block_source: BlockSource) -> Self {
+        self.block_source = block_source;
+        self
+    }
+
+    /// Sets the number of recent blocks tracked to detect reorgs, defaults to `CAP`.
+    ///
+    /// Reorgs deeper than the depth resync the state space instead of unwinding it. Returns
+    /// [`StateSpaceError::InvalidReorgDepth`] if the depth is zero or exceeds `CAP`, the number
+    /// of blocks whose state changes can be unwound.
+    pub fn with_reorg_depth(mut self, depth: usize) -> Result<Self, StateSpaceError<N>> {
+        if depth == 0 || depth > CAP {
+            return Err(StateSpaceError::InvalidReorgDepth {
+                depth,
+                capacity: CAP,
+            });
+        }
+
+        self.reorg_depth = depth;
+        Ok(self)
+    }
+
+    /// Refreshes the share rate and deposit limit of every ERC4626 vault once at least `blocks`
+    /// blocks passed since the last refresh.
+    ///
+    /// Yield accrual, harvests and losses change the share rate without emitting the logs the
+    /// vaults are synced from. The withdraw limit depends on the balance of its owner and is not
+    /// refreshed, see [`ERC4626Vault::sync_limits`](crate::amm::erc_4626::ERC4626Vault::sync_limits).
+    pub fn with_rate_refresh_interval(mut self, blocks: u64) -> Self {
+        self.rate_refresh_interval = Some(blocks);
+        self
+    }
+
+    /// Sets how the AMMs of the state space are stored, defaults to [`StateSpaceBackend::Locked`].
+    pub fn with_backend(mut self, backend: StateSpaceBackend) -> Self {
+        if self.state.backend() != backend {
+            // Until the manager is started, the latest snapshot holds every AMM
+            let amms = self.snapshot_tx.borrow().amms.clone();
+            self.state = StateSpaceStore::new(backend, amms);
+        }
+        self
+    }
+
+    /// Sets the number of updates buffered for each subscriber, defaults to
+    /// [`DEFAULT_BROADCAST_CAPACITY`].
+    ///
+    /// Receivers created before this call stop receiving updates.
+    pub fn with_broadcast_capacity(mut self, capacity: usize) -> Self {
+        self.updates_tx = broadcast::channel(capacity).0;
+        self
+    }
 }
 #[derive(Debug, Clone)]
 pub struct StateChange {
@@ -248,54 +651,172 @@ impl StateChange {
     }
 }
 
+/// State changes applied to the state space in a single block.
+#[derive(Debug, Clone)]
+pub struct BlockStateUpdate {
+    pub block_number: u64,
+    pub block_hash: B256,
+    /// Address, state before and state after the block for each AMM that changed.
+    pub updated: Vec<(Address, AMM, AMM)>,
+    /// AMMs added to the state space by factory creation events.
+    pub added: Vec<AMM>,
+    /// Whether the update results from a reorg.
+    ///
+    /// When a reorg is detected, an update for the common ancestor block is sent first, containing
+    /// the AMMs restored to their state at that block, followed by the updates for the new blocks.
+    pub reorged: bool,
+}
+
+impl BlockStateUpdate {
+    pub fn new(block_number: u64, block_hash: B256) -> Self {
+        Self {
+            block_number,
+            block_hash,
+            updated: vec![],
+            added: vec![],
+            reorged: false,
+        }
+    }
+
+    /// Returns the addresses of all AMMs updated or added in the block.
+    pub fn addresses(&self) -> impl Iterator<Item = Address> + '_ {
+        self.updated
+            .iter()
+            .map(|(address, _, _)| *address)
+            .chain(self.added.iter().map(|amm| amm.address()))
+    }
+}
+
 pub async fn handle_state_changes_from_logs<const CAP: usize, N: Network>(
-    state: Arc<RwLock<StateSpace>>,
+    state: StateSpaceStore,
     state_change_cache: Arc<RwLock<StateChangeCache<CAP>>>,
     logs: Vec<Log>,
 ) -> Result<Vec<Address>, StateSpaceError<N>> {
-    // If there are no logs to process, return early
-    let Some(log) = logs.first() else {
-        return Ok(vec![]);
-    };
+    let updates =
+        handle_block_state_changes_from_logs::<CAP, N>(state, state_change_cache, logs).await?;
 
-    // Track the block number for the most recently processed
-    // log to determine when to commit state changes to cache
-    let mut last_log_block_number = get_block_number_from_log(log)?;
+    // Return the addresses of the amms that were affected
+    let updated_amms = updates
+        .iter()
+        .flat_map(|update| update.addresses())
+        .collect::<HashSet<Address>>();
 
-    let mut prev_state = vec![];
-    let mut updated_amms = HashSet::new();
+    Ok(updated_amms.into_iter().collect())
+}
 
-    // For each log, check if the log is from an amm in the state space and sync the updates
-    for log in logs.into_iter() {
-        let log_block_number = get_block_number_from_log(&log)?;
+/// Syncs the AMMs in the state space from `logs`, which must be ordered by block.
+///
+/// Returns an update for each block that changed the state of at least one AMM.
+pub async fn handle_block_state_changes_from_logs<const CAP: usize, N: Network>(
+    state: StateSpaceStore,
+    state_change_cache: Arc<RwLock<StateChangeCache<CAP>>>,
+    logs: Vec<Log>,
+) -> Result<Vec<BlockStateUpdate>, StateSpaceError<N>> {
+    let mut updates = vec![];
+    let mut logs = logs.into_iter().peekable();
 
-        let log_address = log.address();
-        if let Some(amm) = state.write().await.get_mut(&log_address) {
-            updated_amms.insert(log_address);
+    while let Some(log) = logs.peek() {
+        let block_number = get_block_number_from_log(log)?;
+        let mut update = BlockStateUpdate::new(block_number, log.block_hash.unwrap_or_default());
 
-            // Push the state of the amm before syncing to cache and then update the state
-            prev_state.push(amm.clone());
-            amm.sync_from_log(log)?;
+        let mut block_logs = vec![];
+        while let Some(log) = logs.next_if(|log| log.block_number == Some(block_number)) {
+            block_logs.push(log);
         }
 
-        // If the block number has changed, commit the state changes to the cache
-        if log_block_number != last_log_block_number {
-            commit_state_changes(
-                &mut prev_state,
-                last_log_block_number,
-                state_change_cache.clone(),
-            )
-            .await;
+        // Sync the amms in the state space from the logs of the block
+        update.updated = state.sync_from_logs(block_logs).await?;
+        let mut prev_state: Vec<AMM> = update
+            .updated
+            .iter()
+            .map(|(_, prev_amm, _)| prev_amm.clone())
+            .collect();
 
-            last_log_block_number = log_block_number;
+        // Commit the state changes for the block to the cache
+        commit_state_changes(&mut prev_state, block_number, state_change_cache.clone()).await;
+
+        if !update.updated.is_empty() {
+            updates.push(update);
         }
     }
 
-    // Commit the state changes for the last block
-    commit_state_changes(&mut prev_state, last_log_block_number, state_change_cache).await;
+    Ok(updates)
+}
 
-    // Return the addresses of the amms that were affected
-    Ok(updated_amms.into_iter().collect())
+/// Creates AMMs from the factory creation events in `logs` and inserts them into the state space.
+///
+/// New AMMs are populated at the block of the last log, so any later logs emitted by them are
+/// already reflected in their state and are dropped. Returns the remaining logs and the addresses
+/// of the AMMs added.
+///
+/// AMMs that fail to populate are skipped. AMMs added in a block that is later reorged are not
+/// removed from the state space.
+pub async fn add_amms_from_logs<N, P>(
+    state: StateSpaceStore,
+    factories: &[Factory],
+    logs: Vec<Log>,
+    provider: P,
+) -> Result<(Vec<Log>, Vec<Address>), StateSpaceError<N>>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let Some(last_log) = logs.last() else {
+        return Ok((logs, vec![]));
+    };
+    let block_number = get_block_number_from_log(last_log)?;
+
+    let mut new_amms = vec![];
+    let mut remaining_logs = vec![];
+
+    for log in logs {
+        let factory = log.topics().first().and_then(|event_signature| {
+            factories.iter().find(|factory| {
+                factory.address() == log.address()
+                    && factory.amm_created_event_signature() == *event_signature
+            })
+        });
+
+        let Some(factory) = factory else {
+            // Logs from new AMMs are already reflected in their populated state
+            if !new_amms.contains(&log.address()) {
+                remaining_logs.push(log);
+            }
+            continue;
+        };
+
+        let mut amm = factory.new_empty_amm_from_log(log)?;
+        if state.contains_key(&amm.address()).await {
+            continue;
+        }
+
+        if let Err(err) = amm
+            .populate_data(Some(block_number), provider.clone())
+            .await
+        {
+            tracing::warn!(amm = ?amm.address(), ?err, "failed to populate new AMM, skipping");
+            continue;
+        }
+
+        tracing::debug!(amm = ?amm.address(), block_number, "adding new AMM to state space");
+        new_amms.push(amm.address());
+        state.insert(amm).await;
+    }
+
+    Ok((remaining_logs, new_amms))
+}
+
+/// Returns a filter matching the sync events of every AMM in the state space and the creation
+/// events of `factories`.
+async fn build_filter(state: &StateSpaceStore, factories: &[Factory]) -> Filter {
+    let mut event_signatures = state.sync_event_signatures().await;
+    event_signatures.extend(
+        factories
+            .iter()
+            .map(|factory| factory.amm_created_event_signature()),
+    );
+
+    Filter::new().event_signature(event_signatures.into_iter().collect::<Vec<_>>())
 }
 
 /// Commits state changes contained in `prev_state` to the state change cache
@@ -316,23 +837,116 @@ async fn commit_state_changes<const CAP: usize>(
     prev_state.clear();
 }
 
+/// Syncs the share rate and deposit limit of every ERC4626 vault in the state space at
+/// `block_number`, committing the previous state of the vaults that changed to the cache.
+///
+/// Returns the address, state before and state after of each vault that changed. Vaults that
+/// fail to sync are logged and keep their state. The withdraw limit is left unchanged, as it
+/// depends on the balance of an owner.
+async fn refresh_vault_rates<N, P, const CAP: usize>(
+    state: StateSpaceStore,
+    state_change_cache: Arc<RwLock<StateChangeCache<CAP>>>,
+    block_number: u64,
+    provider: P,
+) -> Vec<(Address, AMM, AMM)>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let vaults = state.amms().await.into_iter().filter_map(|amm| match amm {
+        AMM::ERC4626Vault(vault) => Some(vault),
+        _ => None,
+    });
+
+    let refreshed_vaults = stream::iter(vaults)
+        .map(|prev_vault| {
+            let provider = provider.clone();
+            async move {
+                let mut vault = prev_vault.clone();
+                let result = match vault.sync_rate(Some(block_number), provider.clone()).await {
+                    Ok(()) => vault.sync_limits(None, Some(block_number), provider).await,
+                    Err(err) => Err(err),
+                };
+
+                match result {
+                    Ok(()) => (prev_vault.vault_reserve != vault.vault_reserve
+                        || prev_vault.asset_reserve != vault.asset_reserve
+                        || prev_vault.max_deposit != vault.max_deposit)
+                        .then_some(AMM::ERC4626Vault(vault)),
+                    Err(err) => {
+                        tracing::warn!(vault = ?vault.vault_token, ?err, block_number, "failed to refresh vault rate");
+                        None
+                    }
+                }
+            }
+        })
+        .buffer_unordered(DEFAULT_MAX_CONCURRENT_REQUESTS)
+        .filter_map(futures::future::ready)
+        .collect::<Vec<AMM>>()
+        .await;
+
+    let refreshed_vaults = state.replace(refreshed_vaults).await;
+
+    let mut prev_state: Vec<AMM> = refreshed_vaults
+        .iter()
+        .map(|(_, prev_amm, _)| prev_amm.clone())
+        .collect();
+    commit_state_changes(&mut prev_state, block_number, state_change_cache).await;
+
+    refreshed_vaults
+}
+
 /// Unwinds the state changes up to the specified block number
+///
+/// Returns the latest block still synced and the address, state before and state after
+/// unwinding of each affected AMM.
 async fn unwind_state_changes<const CAP: usize>(
-    state: Arc<RwLock<StateSpace>>,
+    state: StateSpaceStore,
     state_change_cache: Arc<RwLock<StateChangeCache<CAP>>>,
     chain_head_block_number: u64,
-) -> u64 {
+) -> Result<(u64, Vec<(Address, AMM, AMM)>), StateChangeCacheError> {
     let updated_amms = state_change_cache
         .write()
         .await
-        .unwind_state_changes(chain_head_block_number);
+        .unwind_state_changes(chain_head_block_number)?;
 
-    let mut state_writer = state.write().await;
-    for amm in updated_amms {
-        state_writer.insert(amm.address(), amm);
-    }
+    let unwound_amms = state.replace(updated_amms).await;
 
-    chain_head_block_number - 1
+    Ok((chain_head_block_number - 1, unwound_amms))
+}
+
+/// Repopulates the AMMs at `addresses` at `block_number`, or every AMM in the state space if
+/// `None`, and clears the state change cache.
+///
+/// Used to recover from reorgs that cannot be unwound from the cache. Returns the address, state
+/// before and state after resyncing of each AMM.
+async fn resync_state_space<N, P, const CAP: usize>(
+    state: StateSpaceStore,
+    state_change_cache: Arc<RwLock<StateChangeCache<CAP>>>,
+    addresses: Option<HashSet<Address>>,
+    block_number: u64,
+    provider: P,
+) -> Result<Vec<(Address, AMM, AMM)>, StateSpaceError<N>>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let mut amms = match addresses {
+        Some(addresses) => {
+            let mut amms = Vec::with_capacity(addresses.len());
+            for address in addresses {
+                amms.extend(state.get(&address).await);
+            }
+            amms
+        }
+        None => state.amms().await,
+    };
+    populate_amms(&mut amms, block_number, provider).await?;
+
+    let resynced_amms = state.replace(amms).await;
+    state_change_cache.write().await.clear();
+
+    Ok(resynced_amms)
 }
 
 /// Extracts the block number from a log
@@ -343,3 +957,71 @@ pub fn get_block_number_from_log(log: &Log) -> Result<u64, EventLogError> {
         Err(EventLogError::LogBlockNumberNotFound)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amm::uniswap_v2::UniswapV2Pool;
+
+    #[test]
+    fn test_snapshot_is_isolated_from_state_changes() {
+        let address = Address::with_last_byte(1);
+        let mut state: StateSpace = vec![AMM::UniswapV2Pool(UniswapV2Pool {
+            address,
+            reserve_0: 100,
+            ..Default::default()
+        })]
+        .into();
+
+        let snapshot = StateSnapshot {
+            block_number: 1,
+            amms: state.clone(),
+        };
+
+        if let Some(AMM::UniswapV2Pool(pool)) = state.get_mut(&address) {
+            pool.reserve_0 = 200;
+        }
+        state.insert(
+            Address::with_last_byte(2),
+            AMM::UniswapV2Pool(UniswapV2Pool::default()),
+        );
+
+        let Some(AMM::UniswapV2Pool(pool)) = snapshot.get(&address) else {
+            panic!("missing pool in snapshot");
+        };
+        assert_eq!(pool.reserve_0, 100);
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(state.len(), 2);
+    }
+
+    #[test]
+    fn test_apply_update() {
+        let address = Address::with_last_byte(1);
+        let pool = |reserve_0| {
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                address,
+                reserve_0,
+                ..Default::default()
+            })
+        };
+        let other = AMM::UniswapV2Pool(UniswapV2Pool {
+            address: Address::with_last_byte(2),
+            ..Default::default()
+        });
+
+        let mut state: StateSpace = vec![pool(100)].into();
+        let previous = state.clone();
+
+        let mut update = BlockStateUpdate::new(1, B256::ZERO);
+        update.updated.push((address, pool(100), pool(200)));
+        update.added.push(other.clone());
+        state.apply_update(&update);
+
+        let Some(AMM::UniswapV2Pool(updated)) = state.get(&address) else {
+            panic!("missing pool");
+        };
+        assert_eq!(updated.reserve_0, 200);
+        assert!(state.contains_key(&other.address()));
+        assert_eq!(previous.len(), 1);
+    }
+}
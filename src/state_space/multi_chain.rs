@@ -0,0 +1,350 @@
+use std::{
+    collections::BTreeMap,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use alloy::{network::Network, primitives::Address, providers::Provider};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    task::JoinHandle,
+};
+
+use crate::{amm::AMM, chains};
+
+use super::{
+    block_source::BlockSource,
+    error::{MultiChainError, StateSpaceError},
+    BlockStateUpdate, StateSnapshot, StateSpaceManager, DEFAULT_BROADCAST_CAPACITY,
+};
+
+/// Block time assumed for chains missing from the [chain registry](crate::chains).
+pub const DEFAULT_BLOCK_TIME: Duration = Duration::from_secs(12);
+/// Number of recent blocks tracked to detect reorgs on chains missing from the
+/// [chain registry](crate::chains).
+pub const DEFAULT_REORG_DEPTH: usize = 30;
+
+/// How the state space of a single chain follows new blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    /// Average time between blocks, used as the polling interval when polling for blocks
+    pub block_time: Duration,
+    /// Number of recent blocks tracked to detect reorgs, see
+    /// [`StateSpaceManager::with_reorg_depth`]
+    pub reorg_depth: usize,
+    pub block_source: BlockSource,
+}
+
+impl ChainConfig {
+    /// Returns the configuration of `chain_id`, subscribing to new blocks.
+    ///
+    /// The block time and reorg depth are taken from the [chain registry](crate::chains) if the
+    /// chain is known, [`DEFAULT_BLOCK_TIME`] and [`DEFAULT_REORG_DEPTH`] otherwise.
+    pub fn new(chain_id: u64) -> Self {
+        let (block_time, reorg_depth) = chains::chain(chain_id)
+            .map_or((DEFAULT_BLOCK_TIME, DEFAULT_REORG_DEPTH), |chain| {
+                (chain.block_time, chain.reorg_depth)
+            });
+
+        Self {
+            chain_id,
+            block_time,
+            reorg_depth,
+            block_source: BlockSource::Subscription,
+        }
+    }
+
+    pub fn with_block_time(mut self, block_time: Duration) -> Self {
+        self.block_time = block_time;
+        if let BlockSource::Polling { confirmations, .. } = self.block_source {
+            self.block_source = BlockSource::polling(block_time, confirmations);
+        }
+        self
+    }
+
+    pub fn with_reorg_depth(mut self, reorg_depth: usize) -> Self {
+        self.reorg_depth = reorg_depth;
+        self
+    }
+
+    /// Polls for a new block every block time instead of subscribing to new blocks, for
+    /// HTTP-only providers.
+    pub fn with_polling(mut self, confirmations: u64) -> Self {
+        self.block_source = BlockSource::polling(self.block_time, confirmations);
+        self
+    }
+}
+
+/// A [`BlockStateUpdate`] of one of the chains of a [`MultiChainStateSpace`].
+#[derive(Debug, Clone)]
+pub struct ChainStateUpdate {
+    pub chain_id: u64,
+    pub update: Arc<BlockStateUpdate>,
+}
+
+#[derive(Debug)]
+struct ChainStateSpace<N, P, const CAP: usize> {
+    config: ChainConfig,
+    manager: StateSpaceManager<N, P, CAP>,
+    latest_synced_block: u64,
+}
+
+/// Runs a [`StateSpaceManager`] per chain, each with its own provider, behind a single interface
+/// where AMMs are keyed by `(chain_id, address)`.
+#[derive(Debug)]
+pub struct MultiChainStateSpace<N, P, const CAP: usize> {
+    chains: BTreeMap<u64, ChainStateSpace<N, P, CAP>>,
+    /// Fans out the updates of every chain to every subscriber once started
+    updates_tx: broadcast::Sender<ChainStateUpdate>,
+}
+
+impl<N, P, const CAP: usize> Default for MultiChainStateSpace<N, P, CAP>
+where
+    N: Network,
+    P: Provider<N> + Clone + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N, P, const CAP: usize> MultiChainStateSpace<N, P, CAP>
+where
+    N: Network,
+    P: Provider<N> + Clone + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            chains: BTreeMap::new(),
+            updates_tx: broadcast::channel(DEFAULT_BROADCAST_CAPACITY).0,
+        }
+    }
+
+    /// Adds the state space of the chain configured by `config`, synced up to
+    /// `latest_synced_block`, replacing any state space previously added for the chain.
+    ///
+    /// The block source and reorg depth of `manager` are set from `config`. Returns
+    /// [`MultiChainError::ChainConfigError`] if the reorg depth is zero or exceeds `CAP`.
+    pub fn with_chain(
+        mut self,
+        config: ChainConfig,
+        manager: StateSpaceManager<N, P, CAP>,
+        latest_synced_block: u64,
+    ) -> Result<Self, MultiChainError<N>> {
+        let manager = manager
+            .with_block_source(config.block_source)
+            .with_reorg_depth(config.reorg_depth)
+            .map_err(|source| MultiChainError::ChainConfigError {
+                chain_id: config.chain_id,
+                source,
+            })?;
+
+        self.chains.insert(
+            config.chain_id,
+            ChainStateSpace {
+                config,
+                manager,
+                latest_synced_block,
+            },
+        );
+        Ok(self)
+    }
+
+    /// Sets the number of updates buffered for each subscriber, defaults to
+    /// [`DEFAULT_BROADCAST_CAPACITY`].
+    ///
+    /// Receivers created before this call stop receiving updates.
+    pub fn with_broadcast_capacity(mut self, capacity: usize) -> Self {
+        self.updates_tx = broadcast::channel(capacity).0;
+        self
+    }
+
+    /// Returns the ids of the chains added, in ascending order.
+    pub fn chain_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.chains.keys().copied()
+    }
+
+    pub fn config(&self, chain_id: u64) -> Option<&ChainConfig> {
+        self.chains.get(&chain_id).map(|chain| &chain.config)
+    }
+
+    pub fn manager(&self, chain_id: u64) -> Option<&StateSpaceManager<N, P, CAP>> {
+        self.chains.get(&chain_id).map(|chain| &chain.manager)
+    }
+
+    /// Returns the current state of the AMM at `address` on `chain_id`.
+    pub async fn get(&self, (chain_id, address): (u64, Address)) -> Option<AMM> {
+        self.manager(chain_id)?.state().get(&address).await
+    }
+
+    /// Returns the current state of each AMM found, keyed by `(chain_id, address)`.
+    pub async fn get_many(
+        &self,
+        keys: impl IntoIterator<Item = (u64, Address)>,
+    ) -> Vec<((u64, Address), AMM)> {
+        let mut amms = vec![];
+        for key in keys {
+            if let Some(amm) = self.get(key).await {
+                amms.push((key, amm));
+            }
+        }
+        amms
+    }
+
+    /// Returns a snapshot of the state space of `chain_id` at its latest fully synced block.
+    pub fn snapshot(&self, chain_id: u64) -> Option<StateSnapshot> {
+        self.manager(chain_id).map(StateSpaceManager::snapshot)
+    }
+
+    /// Returns a new receiver for the updates of every chain, sent once [started](Self::start).
+    ///
+    /// Updates of a single chain are received in order, updates of different chains are
+    /// interleaved as they are synced. A receiver that falls behind gets
+    /// [`RecvError::Lagged`], see [`StateSpaceManager::subscribe`].
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainStateUpdate> {
+        self.updates_tx.subscribe()
+    }
+
+    /// Returns a new receiver for the updates of `chain_id` only.
+    pub fn subscribe_chain(
+        &self,
+        chain_id: u64,
+    ) -> Option<broadcast::Receiver<Arc<BlockStateUpdate>>> {
+        self.manager(chain_id).map(StateSpaceManager::subscribe)
+    }
+
+    /// [Starts](StateSpaceManager::start) the manager of every chain, forwarding their updates to
+    /// the receivers returned by [`subscribe`](Self::subscribe).
+    ///
+    /// Returns the tasks of each chain with its chain id. If a chain fails to start, the chains
+    /// started before it are shut down so that the state space can be started again.
+    pub async fn start(
+        &self,
+        buffer: usize,
+    ) -> Result<Vec<(u64, JoinHandle<Result<(), StateSpaceError<N>>>)>, MultiChainError<N>> {
+        let mut handles = vec![];
+
+        for (&chain_id, chain) in self.chains.iter() {
+            // Subscribe before starting to forward every update
+            let mut chain_rx = chain.manager.subscribe();
+            let chain_handles = match chain.manager.start(chain.latest_synced_block, buffer).await {
+                Ok(chain_handles) => chain_handles,
+                Err(source) => {
+                    self.shutdown(handles).await;
+                    return Err(MultiChainError::ChainStartError { chain_id, source });
+                }
+            };
+
+            let updates_tx = self.updates_tx.clone();
+            let forward_handle = tokio::spawn(async move {
+                loop {
+                    match chain_rx.recv().await {
+                        Ok(update) => {
+                            // Sending only fails when there are no receivers, in which case the update is dropped
+                            let _ = updates_tx.send(ChainStateUpdate { chain_id, update });
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            tracing::warn!(chain_id, skipped, "dropped state updates");
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+
+                Ok::<(), StateSpaceError<N>>(())
+            });
+
+            handles.extend(
+                chain_handles
+                    .into_iter()
+                    .chain([forward_handle])
+                    .map(|handle| (chain_id, handle)),
+            );
+
+            tracing::info!(
+                chain_id,
+                latest_synced_block = chain.latest_synced_block,
+                "started state space"
+            );
+        }
+
+        Ok(handles)
+    }
+
+    /// Stops the tasks of the chains started, allowing their managers to be started again.
+    async fn shutdown(&self, handles: Vec<(u64, JoinHandle<Result<(), StateSpaceError<N>>>)>) {
+        let mut chain_ids = vec![];
+        for (chain_id, handle) in handles {
+            handle.abort();
+            // Wait for the task to stop, the cancellation error is expected
+            let _ = handle.await;
+
+            if !chain_ids.contains(&chain_id) {
+                chain_ids.push(chain_id);
+            }
+        }
+
+        for chain_id in chain_ids {
+            if let Some(chain) = self.chains.get(&chain_id) {
+                chain.manager.listening.store(false, Ordering::Release);
+            }
+
+            tracing::info!(chain_id, "stopped state space");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::providers::ProviderBuilder;
+
+    #[test]
+    fn test_chain_config() {
+        let config = ChainConfig::new(chains::ARBITRUM_CHAIN_ID);
+        assert_eq!(config.block_time, Duration::from_millis(250));
+        assert_eq!(config.reorg_depth, chains::ARBITRUM.reorg_depth);
+        assert_eq!(config.block_source, BlockSource::Subscription);
+
+        assert_eq!(ChainConfig::new(0).block_time, DEFAULT_BLOCK_TIME);
+        assert_eq!(ChainConfig::new(0).reorg_depth, DEFAULT_REORG_DEPTH);
+
+        // The polling interval follows the block time
+        let config = ChainConfig::new(chains::BASE_CHAIN_ID)
+            .with_polling(1)
+            .with_block_time(Duration::from_secs(1));
+        assert_eq!(
+            config.block_source,
+            BlockSource::polling(Duration::from_secs(1), 1)
+        );
+    }
+
+    #[test]
+    fn test_invalid_reorg_depth() {
+        let provider = ProviderBuilder::new().on_http("http://localhost:8545".parse().unwrap());
+        let manager = || StateSpaceManager::new(vec![], provider.clone());
+
+        // The depth cannot exceed the capacity of the state change cache
+        let config = ChainConfig::new(chains::ETHEREUM_CHAIN_ID).with_reorg_depth(31);
+        assert!(matches!(
+            MultiChainStateSpace::new().with_chain(config, manager(), 0),
+            Err(MultiChainError::ChainConfigError {
+                chain_id: chains::ETHEREUM_CHAIN_ID,
+                source: StateSpaceError::InvalidReorgDepth {
+                    depth: 31,
+                    capacity: 30
+                }
+            })
+        ));
+
+        let config = ChainConfig::new(chains::ETHEREUM_CHAIN_ID).with_reorg_depth(0);
+        assert!(MultiChainStateSpace::new()
+            .with_chain(config, manager(), 0)
+            .is_err());
+
+        let state_space = MultiChainStateSpace::new()
+            .with_chain(ChainConfig::new(chains::ETHEREUM_CHAIN_ID), manager(), 0)
+            .unwrap();
+        assert_eq!(state_space.chain_ids().collect::<Vec<_>>(), [1]);
+    }
+}
@@ -0,0 +1,891 @@
+use alloy::{
+    primitives::{Address, Bytes, FixedBytes, B256, I256, U256},
+    sol,
+    sol_types::SolCall,
+};
+
+use crate::amm::{
+    uniswap_v2::{
+        factory::{compute_pair_address, KNOWN_UNISWAP_V2_FORKS},
+        IUniswapV2Pair,
+    },
+    uniswap_v3::{
+        factory::{compute_pool_address, UNISWAP_V3_POOL_INIT_CODE_HASH},
+        IUniswapV3Pool,
+    },
+    AutomatedMarketMaker, AMM,
+};
+
+use super::{error::PendingTxError, StateSpace};
+
+sol! {
+    /// Swap functions of the UniswapV2Router02
+    #[derive(Debug, PartialEq, Eq)]
+    contract IUniswapV2Router {
+        function swapExactTokensForTokens(uint256 amountIn, uint256 amountOutMin, address[] path, address to, uint256 deadline) external returns (uint256[] amounts);
+        function swapTokensForExactTokens(uint256 amountOut, uint256 amountInMax, address[] path, address to, uint256 deadline) external returns (uint256[] amounts);
+        function swapExactETHForTokens(uint256 amountOutMin, address[] path, address to, uint256 deadline) external payable returns (uint256[] amounts);
+        function swapTokensForExactETH(uint256 amountOut, uint256 amountInMax, address[] path, address to, uint256 deadline) external returns (uint256[] amounts);
+        function swapExactTokensForETH(uint256 amountIn, uint256 amountOutMin, address[] path, address to, uint256 deadline) external returns (uint256[] amounts);
+        function swapETHForExactTokens(uint256 amountOut, address[] path, address to, uint256 deadline) external payable returns (uint256[] amounts);
+        function swapExactTokensForTokensSupportingFeeOnTransferTokens(uint256 amountIn, uint256 amountOutMin, address[] path, address to, uint256 deadline) external;
+        function swapExactETHForTokensSupportingFeeOnTransferTokens(uint256 amountOutMin, address[] path, address to, uint256 deadline) external payable;
+        function swapExactTokensForETHSupportingFeeOnTransferTokens(uint256 amountIn, uint256 amountOutMin, address[] path, address to, uint256 deadline) external;
+    }
+}
+
+sol! {
+    /// Exact input functions of the UniswapV3 SwapRouter
+    #[derive(Debug, PartialEq, Eq)]
+    contract ISwapRouter {
+        struct ExactInputSingleParams {
+            address tokenIn;
+            address tokenOut;
+            uint24 fee;
+            address recipient;
+            uint256 deadline;
+            uint256 amountIn;
+            uint256 amountOutMinimum;
+            uint160 sqrtPriceLimitX96;
+        }
+
+        struct ExactInputParams {
+            bytes path;
+            address recipient;
+            uint256 deadline;
+            uint256 amountIn;
+            uint256 amountOutMinimum;
+        }
+
+        function exactInputSingle(ExactInputSingleParams params) external payable returns (uint256 amountOut);
+        function exactInput(ExactInputParams params) external payable returns (uint256 amountOut);
+        function multicall(bytes[] data) external payable returns (bytes[] results);
+    }
+}
+
+sol! {
+    /// Exact input functions of the UniswapV3 SwapRouter02, whose params have no deadline
+    #[derive(Debug, PartialEq, Eq)]
+    contract ISwapRouter02 {
+        struct ExactInputSingleParams {
+            address tokenIn;
+            address tokenOut;
+            uint24 fee;
+            address recipient;
+            uint256 amountIn;
+            uint256 amountOutMinimum;
+            uint160 sqrtPriceLimitX96;
+        }
+
+        struct ExactInputParams {
+            bytes path;
+            address recipient;
+            uint256 amountIn;
+            uint256 amountOutMinimum;
+        }
+
+        function exactInputSingle(ExactInputSingleParams params) external payable returns (uint256 amountOut);
+        function exactInput(ExactInputParams params) external payable returns (uint256 amountOut);
+        function multicall(uint256 deadline, bytes[] data) external payable returns (bytes[] results);
+        function refundETH() external payable;
+        function unwrapWETH9(uint256 amountMinimum, address recipient) external payable;
+        function unwrapWETH9(uint256 amountMinimum) external payable;
+        function sweepToken(address token, uint256 amountMinimum, address recipient) external payable;
+        function sweepToken(address token, uint256 amountMinimum) external payable;
+    }
+}
+
+/// Which router interface a [`SwapRouter`] exposes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouterKind {
+    /// UniswapV2Router02 and its forks.
+    UniswapV2,
+    /// UniswapV3 SwapRouter and SwapRouter02, exact input swaps only.
+    UniswapV3,
+}
+
+/// A router whose calldata can be simulated, swapping through the pools created by `factory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapRouter {
+    pub address: Address,
+    pub factory: Address,
+    pub kind: RouterKind,
+    /// Pool init code hash of `factory`, used to compute the pool address of each hop
+    pub init_code_hash: B256,
+}
+
+impl SwapRouter {
+    /// Returns a router of the Uniswap factory `factory`, see
+    /// [`with_init_code_hash`](Self::with_init_code_hash) for forks.
+    pub fn new(address: Address, factory: Address, kind: RouterKind) -> Self {
+        let init_code_hash = match kind {
+            RouterKind::UniswapV2 => KNOWN_UNISWAP_V2_FORKS[0].init_code_hash,
+            RouterKind::UniswapV3 => UNISWAP_V3_POOL_INIT_CODE_HASH,
+        };
+
+        SwapRouter {
+            address,
+            factory,
+            kind,
+            init_code_hash,
+        }
+    }
+
+    pub fn with_init_code_hash(mut self, init_code_hash: B256) -> Self {
+        self.init_code_hash = init_code_hash;
+        self
+    }
+}
+
+/// The fields of a pending transaction needed to simulate it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PendingTx {
+    pub to: Address,
+    pub value: U256,
+    pub input: Bytes,
+}
+
+impl PendingTx {
+    pub fn new(to: Address, value: U256, input: Bytes) -> Self {
+        PendingTx { to, value, input }
+    }
+}
+
+/// A swap applied to an AMM while simulating a pending transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedSwap {
+    pub amm: Address,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub amount_out: U256,
+}
+
+/// The state space after a pending transaction and the swaps it made, in execution order.
+#[derive(Debug, Clone)]
+pub struct PendingTxSimulation {
+    pub state: StateSpace,
+    pub swaps: Vec<SimulatedSwap>,
+}
+
+/// Simulates `tx` against a copy of `state`, returning the state after the transaction.
+///
+/// Supported transactions are the swap functions of the UniswapV2 router, exact input swaps and
+/// multicalls of the UniswapV3 routers, and direct `swap` calls to UniswapV2 and UniswapV3 pools
+/// as built by `swap_calldata`. Router swaps are only routed through pools in `state` created by
+/// the router's factory. Returns an error if the transaction would revert or cannot be
+/// simulated, in which case the state is unchanged.
+///
+/// The simulation relies on [`simulate_swap_mut`](AutomatedMarketMaker::simulate_swap_mut):
+/// fee-on-transfer tokens are treated as regular tokens and the UniswapV3 price limit is ignored.
+pub fn simulate_pending_tx(
+    state: &StateSpace,
+    routers: &[SwapRouter],
+    tx: &PendingTx,
+) -> Result<PendingTxSimulation, PendingTxError> {
+    let mut simulation = PendingTxSimulation {
+        state: state.clone(),
+        swaps: vec![],
+    };
+
+    if simulation.state.contains_key(&tx.to) {
+        simulation.apply_pool_call(tx.to, &tx.input)?;
+    } else if let Some(router) = routers.iter().find(|router| router.address == tx.to) {
+        match router.kind {
+            RouterKind::UniswapV2 => {
+                simulation.apply_v2_router_call(router, tx.value, &tx.input)?
+            }
+            RouterKind::UniswapV3 => simulation.apply_v3_router_call(router, &tx.input)?,
+        }
+    } else {
+        return Err(PendingTxError::UnknownTarget(tx.to));
+    }
+
+    Ok(simulation)
+}
+
+/// A swap through a single pool.
+#[derive(Debug, Clone, Copy)]
+struct Hop {
+    pool: Address,
+    token_in: Address,
+    token_out: Address,
+}
+
+impl PendingTxSimulation {
+    fn apply_pool_call(&mut self, pool: Address, input: &[u8]) -> Result<(), PendingTxError> {
+        match self.state.get(&pool) {
+            Some(AMM::UniswapV2Pool(uniswap_v2_pool)) => {
+                let call = IUniswapV2Pair::swapCall::abi_decode(input, true)?;
+
+                let reserve_0 = U256::from(uniswap_v2_pool.reserve_0);
+                let reserve_1 = U256::from(uniswap_v2_pool.reserve_1);
+                let (hop, amount_out, reserve_in, reserve_out) =
+                    match (call.amount0Out.is_zero(), call.amount1Out.is_zero()) {
+                        (true, false) => (
+                            Hop {
+                                pool,
+                                token_in: uniswap_v2_pool.token_a,
+                                token_out: uniswap_v2_pool.token_b,
+                            },
+                            call.amount1Out,
+                            reserve_0,
+                            reserve_1,
+                        ),
+                        (false, true) => (
+                            Hop {
+                                pool,
+                                token_in: uniswap_v2_pool.token_b,
+                                token_out: uniswap_v2_pool.token_a,
+                            },
+                            call.amount0Out,
+                            reserve_1,
+                            reserve_0,
+                        ),
+                        // Flash swaps and swaps without output cannot be simulated from calldata
+                        _ => return Err(PendingTxError::UnsupportedCall(selector(input))),
+                    };
+
+                let amount_in = uniswap_v2_pool.get_amount_in(amount_out, reserve_in, reserve_out);
+                if amount_in.is_zero() {
+                    return Err(PendingTxError::InsufficientLiquidity(pool));
+                }
+
+                self.swap_v2_exact_amounts(hop, amount_in, amount_out)?;
+            }
+            Some(AMM::UniswapV3Pool(uniswap_v3_pool)) => {
+                let call = IUniswapV3Pool::swapCall::abi_decode(input, true)?;
+
+                // A negative amount specifies the exact amount out
+                if call.amountSpecified <= I256::ZERO {
+                    return Err(PendingTxError::UnsupportedExactOutput(pool));
+                }
+
+                let (token_in, token_out) = if call.zeroForOne {
+                    (uniswap_v3_pool.token_a, uniswap_v3_pool.token_b)
+                } else {
+                    (uniswap_v3_pool.token_b, uniswap_v3_pool.token_a)
+                };
+
+                self.swap(
+                    Hop {
+                        pool,
+                        token_in,
+                        token_out,
+                    },
+                    call.amountSpecified.into_raw(),
+                )?;
+            }
+            _ => return Err(PendingTxError::UnsupportedCall(selector(input))),
+        }
+
+        Ok(())
+    }
+
+    fn apply_v2_router_call(
+        &mut self,
+        router: &SwapRouter,
+        value: U256,
+        input: &[u8],
+    ) -> Result<(), PendingTxError> {
+        use IUniswapV2Router as router;
+
+        match selector(input).0 {
+            router::swapExactTokensForTokensCall::SELECTOR => {
+                let call = router::swapExactTokensForTokensCall::abi_decode(input, true)?;
+                let hops = self.resolve_v2_path(router, &call.path)?;
+                self.swap_exact_input(&hops, call.amountIn, call.amountOutMin)?;
+            }
+            router::swapExactTokensForTokensSupportingFeeOnTransferTokensCall::SELECTOR => {
+                let call =
+                    router::swapExactTokensForTokensSupportingFeeOnTransferTokensCall::abi_decode(
+                        input, true,
+                    )?;
+                let hops = self.resolve_v2_path(router, &call.path)?;
+                self.swap_exact_input(&hops, call.amountIn, call.amountOutMin)?;
+            }
+            router::swapExactTokensForETHCall::SELECTOR => {
+                let call = router::swapExactTokensForETHCall::abi_decode(input, true)?;
+                let hops = self.resolve_v2_path(router, &call.path)?;
+                self.swap_exact_input(&hops, call.amountIn, call.amountOutMin)?;
+            }
+            router::swapExactTokensForETHSupportingFeeOnTransferTokensCall::SELECTOR => {
+                let call =
+                    router::swapExactTokensForETHSupportingFeeOnTransferTokensCall::abi_decode(
+                        input, true,
+                    )?;
+                let hops = self.resolve_v2_path(router, &call.path)?;
+                self.swap_exact_input(&hops, call.amountIn, call.amountOutMin)?;
+            }
+            router::swapExactETHForTokensCall::SELECTOR => {
+                let call = router::swapExactETHForTokensCall::abi_decode(input, true)?;
+                let hops = self.resolve_v2_path(router, &call.path)?;
+                self.swap_exact_input(&hops, value, call.amountOutMin)?;
+            }
+            router::swapExactETHForTokensSupportingFeeOnTransferTokensCall::SELECTOR => {
+                let call =
+                    router::swapExactETHForTokensSupportingFeeOnTransferTokensCall::abi_decode(
+                        input, true,
+                    )?;
+                let hops = self.resolve_v2_path(router, &call.path)?;
+                self.swap_exact_input(&hops, value, call.amountOutMin)?;
+            }
+            router::swapTokensForExactTokensCall::SELECTOR => {
+                let call = router::swapTokensForExactTokensCall::abi_decode(input, true)?;
+                let hops = self.resolve_v2_path(router, &call.path)?;
+                self.swap_exact_output_v2(&hops, call.amountOut, call.amountInMax)?;
+            }
+            router::swapTokensForExactETHCall::SELECTOR => {
+                let call = router::swapTokensForExactETHCall::abi_decode(input, true)?;
+                let hops = self.resolve_v2_path(router, &call.path)?;
+                self.swap_exact_output_v2(&hops, call.amountOut, call.amountInMax)?;
+            }
+            router::swapETHForExactTokensCall::SELECTOR => {
+                let call = router::swapETHForExactTokensCall::abi_decode(input, true)?;
+                let hops = self.resolve_v2_path(router, &call.path)?;
+                self.swap_exact_output_v2(&hops, call.amountOut, value)?;
+            }
+            _ => return Err(PendingTxError::UnsupportedCall(selector(input))),
+        }
+
+        Ok(())
+    }
+
+    fn apply_v3_router_call(
+        &mut self,
+        router: &SwapRouter,
+        input: &[u8],
+    ) -> Result<(), PendingTxError> {
+        match selector(input).0 {
+            ISwapRouter::exactInputSingleCall::SELECTOR => {
+                let params = ISwapRouter::exactInputSingleCall::abi_decode(input, true)?.params;
+                let hop = self.resolve_v3_hop(
+                    router,
+                    params.tokenIn,
+                    params.fee.to::<u32>(),
+                    params.tokenOut,
+                )?;
+                self.swap_exact_input(&[hop], params.amountIn, params.amountOutMinimum)?;
+            }
+            ISwapRouter02::exactInputSingleCall::SELECTOR => {
+                let params = ISwapRouter02::exactInputSingleCall::abi_decode(input, true)?.params;
+                let hop = self.resolve_v3_hop(
+                    router,
+                    params.tokenIn,
+                    params.fee.to::<u32>(),
+                    params.tokenOut,
+                )?;
+                self.swap_exact_input(&[hop], params.amountIn, params.amountOutMinimum)?;
+            }
+            ISwapRouter::exactInputCall::SELECTOR => {
+                let params = ISwapRouter::exactInputCall::abi_decode(input, true)?.params;
+                let hops = self.resolve_v3_path(router, &params.path)?;
+                self.swap_exact_input(&hops, params.amountIn, params.amountOutMinimum)?;
+            }
+            ISwapRouter02::exactInputCall::SELECTOR => {
+                let params = ISwapRouter02::exactInputCall::abi_decode(input, true)?.params;
+                let hops = self.resolve_v3_path(router, &params.path)?;
+                self.swap_exact_input(&hops, params.amountIn, params.amountOutMinimum)?;
+            }
+            ISwapRouter::multicallCall::SELECTOR => {
+                let call = ISwapRouter::multicallCall::abi_decode(input, true)?;
+                for data in call.data {
+                    self.apply_v3_router_call(router, &data)?;
+                }
+            }
+            ISwapRouter02::multicallCall::SELECTOR => {
+                let call = ISwapRouter02::multicallCall::abi_decode(input, true)?;
+                for data in call.data {
+                    self.apply_v3_router_call(router, &data)?;
+                }
+            }
+            // Calls settling the output of swaps within a multicall do not touch any pool
+            ISwapRouter02::refundETHCall::SELECTOR
+            | ISwapRouter02::unwrapWETH9_0Call::SELECTOR
+            | ISwapRouter02::unwrapWETH9_1Call::SELECTOR
+            | ISwapRouter02::sweepToken_0Call::SELECTOR
+            | ISwapRouter02::sweepToken_1Call::SELECTOR => {}
+            _ => return Err(PendingTxError::UnsupportedCall(selector(input))),
+        }
+
+        Ok(())
+    }
+
+    /// Swaps `amount_in` through `hops`, failing if the final amount out is below `amount_out_min`.
+    fn swap_exact_input(
+        &mut self,
+        hops: &[Hop],
+        amount_in: U256,
+        amount_out_min: U256,
+    ) -> Result<U256, PendingTxError> {
+        let mut amount = amount_in;
+        for hop in hops {
+            amount = self.swap(*hop, amount)?;
+        }
+
+        if amount < amount_out_min {
+            return Err(PendingTxError::InsufficientOutputAmount {
+                amount_out: amount,
+                amount_out_min,
+            });
+        }
+
+        Ok(amount)
+    }
+
+    /// Swaps through UniswapV2 `hops` to receive exactly `amount_out`, computing the amounts in
+    /// backwards like the router does.
+    fn swap_exact_output_v2(
+        &mut self,
+        hops: &[Hop],
+        amount_out: U256,
+        amount_in_max: U256,
+    ) -> Result<U256, PendingTxError> {
+        // The amount into each hop followed by the final amount out, as in `getAmountsIn`
+        let mut amounts = vec![U256::ZERO; hops.len() + 1];
+        amounts[hops.len()] = amount_out;
+        for (i, hop) in hops.iter().enumerate().rev() {
+            let Some(AMM::UniswapV2Pool(pool)) = self.state.get(&hop.pool) else {
+                return Err(PendingTxError::InvalidPath);
+            };
+
+            let (reserve_in, reserve_out) = if pool.token_a == hop.token_in {
+                (pool.reserve_0, pool.reserve_1)
+            } else {
+                (pool.reserve_1, pool.reserve_0)
+            };
+
+            amounts[i] = pool.get_amount_in(
+                amounts[i + 1],
+                U256::from(reserve_in),
+                U256::from(reserve_out),
+            );
+            if amounts[i].is_zero() {
+                return Err(PendingTxError::InsufficientLiquidity(hop.pool));
+            }
+        }
+
+        let amount_in = amounts[0];
+        if amount_in > amount_in_max {
+            return Err(PendingTxError::ExcessiveInputAmount {
+                amount_in,
+                amount_in_max,
+            });
+        }
+
+        for (hop, amounts) in hops.iter().zip(amounts.windows(2)) {
+            self.swap_v2_exact_amounts(*hop, amounts[0], amounts[1])?;
+        }
+
+        Ok(amount_in)
+    }
+
+    /// Applies a UniswapV2 swap of `amount_in` for exactly `amount_out` to the state.
+    ///
+    /// The pair sends the requested amount out rather than the amount out of `amount_in`, which
+    /// may be slightly larger due to rounding.
+    fn swap_v2_exact_amounts(
+        &mut self,
+        hop: Hop,
+        amount_in: U256,
+        amount_out: U256,
+    ) -> Result<(), PendingTxError> {
+        let Some(AMM::UniswapV2Pool(pool)) = self.state.get_mut(&hop.pool) else {
+            return Err(PendingTxError::InvalidPath);
+        };
+
+        let (reserve_in, reserve_out) = if pool.token_a == hop.token_in {
+            (&mut pool.reserve_0, &mut pool.reserve_1)
+        } else {
+            (&mut pool.reserve_1, &mut pool.reserve_0)
+        };
+
+        let next_reserve_in = checked_v2_reserve(*reserve_in, amount_in)
+            .ok_or(PendingTxError::ReserveOverflow(hop.pool))?;
+        let next_reserve_out = u128::try_from(amount_out)
+            .ok()
+            .and_then(|amount_out| reserve_out.checked_sub(amount_out))
+            .filter(|reserve_out| *reserve_out > 0)
+            .ok_or(PendingTxError::InsufficientLiquidity(hop.pool))?;
+
+        *reserve_in = next_reserve_in;
+        *reserve_out = next_reserve_out;
+
+        self.swaps.push(SimulatedSwap {
+            amm: hop.pool,
+            token_in: hop.token_in,
+            token_out: hop.token_out,
+            amount_in,
+            amount_out,
+        });
+
+        Ok(())
+    }
+
+    /// Applies a single swap to the state, returning the amount out.
+    fn swap(&mut self, hop: Hop, amount_in: U256) -> Result<U256, PendingTxError> {
+        let Some(amm) = self.state.get_mut(&hop.pool) else {
+            return Err(PendingTxError::InvalidPath);
+        };
+
+        // UniswapV2 reserves are stored as uint112, the swap reverts if they overflow
+        if let AMM::UniswapV2Pool(pool) = &*amm {
+            let reserve_in = if pool.token_a == hop.token_in {
+                pool.reserve_0
+            } else {
+                pool.reserve_1
+            };
+
+            if checked_v2_reserve(reserve_in, amount_in).is_none() {
+                return Err(PendingTxError::ReserveOverflow(hop.pool));
+            }
+        }
+
+        let amount_out = amm.simulate_swap_mut(hop.token_in, hop.token_out, amount_in)?;
+        if amount_out.is_zero() {
+            return Err(PendingTxError::InsufficientLiquidity(hop.pool));
+        }
+
+        self.swaps.push(SimulatedSwap {
+            amm: hop.pool,
+            token_in: hop.token_in,
+            token_out: hop.token_out,
+            amount_in,
+            amount_out,
+        });
+
+        Ok(amount_out)
+    }
+
+    fn resolve_v2_path(
+        &self,
+        router: &SwapRouter,
+        path: &[Address],
+    ) -> Result<Vec<Hop>, PendingTxError> {
+        if path.len() < 2 {
+            return Err(PendingTxError::InvalidPath);
+        }
+
+        path.windows(2)
+            .map(|tokens| self.find_pool(router, tokens[0], tokens[1], None))
+            .collect()
+    }
+
+    fn resolve_v3_hop(
+        &self,
+        router: &SwapRouter,
+        token_in: Address,
+        fee: u32,
+        token_out: Address,
+    ) -> Result<Hop, PendingTxError> {
+        self.find_pool(router, token_in, token_out, Some(fee))
+    }
+
+    /// Resolves a UniswapV3 path, encoded as `token (20 bytes) | fee (3 bytes) | token | ...`.
+    fn resolve_v3_path(
+        &self,
+        router: &SwapRouter,
+        path: &[u8],
+    ) -> Result<Vec<Hop>, PendingTxError> {
+        const ADDRESS_SIZE: usize = 20;
+        const HOP_SIZE: usize = ADDRESS_SIZE + 3;
+
+        if path.len() < ADDRESS_SIZE + HOP_SIZE || (path.len() - ADDRESS_SIZE) % HOP_SIZE != 0 {
+            return Err(PendingTxError::InvalidPath);
+        }
+
+        (0..(path.len() - ADDRESS_SIZE) / HOP_SIZE)
+            .map(|i| {
+                let offset = i * HOP_SIZE;
+                let token_in = Address::from_slice(&path[offset..offset + ADDRESS_SIZE]);
+                let fee = u32::from_be_bytes([
+                    0,
+                    path[offset + ADDRESS_SIZE],
+                    path[offset + ADDRESS_SIZE + 1],
+                    path[offset + ADDRESS_SIZE + 2],
+                ]);
+                let token_out =
+                    Address::from_slice(&path[offset + HOP_SIZE..offset + HOP_SIZE + ADDRESS_SIZE]);
+
+                self.resolve_v3_hop(router, token_in, fee, token_out)
+            })
+            .collect()
+    }
+
+    /// Finds the pool created by the router's factory for the token pair, a UniswapV3 pool if `fee`
+    /// is set or a UniswapV2 pool otherwise.
+    ///
+    /// The pool address is computed from the factory and init code hash, so the lookup does not
+    /// depend on the size of the state space.
+    fn find_pool(
+        &self,
+        router: &SwapRouter,
+        token_in: Address,
+        token_out: Address,
+        fee: Option<u32>,
+    ) -> Result<Hop, PendingTxError> {
+        let is_pair = |token_a: Address, token_b: Address| {
+            (token_a == token_in && token_b == token_out)
+                || (token_a == token_out && token_b == token_in)
+        };
+
+        let address = match fee {
+            Some(fee) => compute_pool_address(
+                router.factory,
+                router.init_code_hash,
+                token_in,
+                token_out,
+                fee,
+            ),
+            None => {
+                compute_pair_address(router.factory, router.init_code_hash, token_in, token_out)
+            }
+        };
+
+        let found = match (self.state.get(&address), fee) {
+            (Some(AMM::UniswapV2Pool(pool)), None) => is_pair(pool.token_a, pool.token_b),
+            (Some(AMM::UniswapV3Pool(pool)), Some(fee)) => {
+                pool.fee == fee && is_pair(pool.token_a, pool.token_b)
+            }
+            _ => false,
+        };
+
+        if !found {
+            return Err(PendingTxError::PoolNotFound {
+                token_in,
+                token_out,
+            });
+        }
+
+        Ok(Hop {
+            pool: address,
+            token_in,
+            token_out,
+        })
+    }
+}
+
+/// Returns the UniswapV2 reserve after `amount_in` is added to `reserve`, or `None` if it overflows
+/// the `uint112` the pair stores its reserves in.
+fn checked_v2_reserve(reserve: u128, amount_in: U256) -> Option<u128> {
+    const MAX_RESERVE: u128 = (1 << 112) - 1;
+
+    amount_in
+        .checked_add(U256::from(reserve))
+        .filter(|reserve| *reserve <= U256::from(MAX_RESERVE))
+        .map(|reserve| reserve.to::<u128>())
+}
+
+/// Returns the function selector of `input`, zero padded if `input` is shorter than 4 bytes.
+fn selector(input: &[u8]) -> FixedBytes<4> {
+    let mut selector = FixedBytes::ZERO;
+    let len = input.len().min(4);
+    selector[..len].copy_from_slice(&input[..len]);
+    selector
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amm::uniswap_v2::UniswapV2Pool;
+    use alloy::primitives::address;
+
+    const FACTORY: Address = address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f");
+    const ROUTER: Address = address!("7a250d5630B4cF539739dF2C5dAcb4c659F2488D");
+    const POOL: Address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+    const USDC: Address = address!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+    const WETH: Address = address!("c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+
+    fn state_space() -> StateSpace {
+        vec![AMM::UniswapV2Pool(UniswapV2Pool {
+            address: POOL,
+            factory_address: Some(FACTORY),
+            token_a: USDC,
+            token_a_decimals: 6,
+            token_b: WETH,
+            token_b_decimals: 18,
+            reserve_0: 40_000_000_000_000,
+            reserve_1: 10_000_000_000_000_000_000_000,
+            fee: 300,
+        })]
+        .into()
+    }
+
+    fn reserves(state: &StateSpace) -> (u128, u128) {
+        let Some(AMM::UniswapV2Pool(pool)) = state.get(&POOL) else {
+            panic!("missing pool");
+        };
+        (pool.reserve_0, pool.reserve_1)
+    }
+
+    #[test]
+    fn test_simulate_v2_router_swap() {
+        let state = state_space();
+        let routers = [SwapRouter::new(ROUTER, FACTORY, RouterKind::UniswapV2)];
+
+        let amount_in = U256::from(1_000_000_000_u128);
+        let input = IUniswapV2Router::swapExactTokensForTokensCall {
+            amountIn: amount_in,
+            amountOutMin: U256::ZERO,
+            path: vec![USDC, WETH],
+            to: Address::ZERO,
+            deadline: U256::MAX,
+        }
+        .abi_encode();
+
+        let simulation = simulate_pending_tx(
+            &state,
+            &routers,
+            &PendingTx::new(ROUTER, U256::ZERO, input.into()),
+        )
+        .unwrap();
+
+        let (reserve_0, reserve_1) = reserves(&state);
+        let swap = &simulation.swaps[0];
+        assert_eq!(simulation.swaps.len(), 1);
+        assert_eq!((swap.token_in, swap.token_out), (USDC, WETH));
+        assert_eq!(
+            reserves(&simulation.state),
+            (
+                reserve_0 + amount_in.to::<u128>(),
+                reserve_1 - swap.amount_out.to::<u128>()
+            )
+        );
+
+        // The original state is untouched
+        assert_eq!(reserves(&state), (reserve_0, reserve_1));
+
+        // A swap that would revert on slippage leaves no post-state
+        let input = IUniswapV2Router::swapExactTokensForTokensCall {
+            amountIn: amount_in,
+            amountOutMin: U256::MAX,
+            path: vec![USDC, WETH],
+            to: Address::ZERO,
+            deadline: U256::MAX,
+        }
+        .abi_encode();
+        assert!(matches!(
+            simulate_pending_tx(
+                &state,
+                &routers,
+                &PendingTx::new(ROUTER, U256::ZERO, input.into())
+            ),
+            Err(PendingTxError::InsufficientOutputAmount { .. })
+        ));
+    }
+
+    #[test]
+    fn test_simulate_v2_pool_swap() {
+        let state = state_space();
+        let Some(AMM::UniswapV2Pool(pool)) = state.get(&POOL) else {
+            panic!("missing pool");
+        };
+
+        let amount_out = U256::from(1_000_000_000_000_000_000_u128);
+        let input = pool
+            .swap_calldata(U256::ZERO, amount_out, Address::ZERO, vec![])
+            .unwrap();
+
+        let simulation =
+            simulate_pending_tx(&state, &[], &PendingTx::new(POOL, U256::ZERO, input)).unwrap();
+
+        let (reserve_0, reserve_1) = reserves(&state);
+        let swap = &simulation.swaps[0];
+        assert_eq!((swap.token_in, swap.token_out), (USDC, WETH));
+        assert_eq!(swap.amount_out, amount_out);
+        assert_eq!(
+            reserves(&simulation.state),
+            (
+                reserve_0 + swap.amount_in.to::<u128>(),
+                reserve_1 - amount_out.to::<u128>()
+            )
+        );
+    }
+
+    #[test]
+    fn test_simulate_v2_router_exact_output_swap() {
+        let state = state_space();
+        let routers = [SwapRouter::new(ROUTER, FACTORY, RouterKind::UniswapV2)];
+
+        let amount_out = U256::from(1_000_000_000_000_000_000_u128);
+        let input = IUniswapV2Router::swapTokensForExactTokensCall {
+            amountOut: amount_out,
+            amountInMax: U256::MAX,
+            path: vec![USDC, WETH],
+            to: Address::ZERO,
+            deadline: U256::MAX,
+        }
+        .abi_encode();
+
+        let simulation = simulate_pending_tx(
+            &state,
+            &routers,
+            &PendingTx::new(ROUTER, U256::ZERO, input.into()),
+        )
+        .unwrap();
+
+        let (reserve_0, reserve_1) = reserves(&state);
+        let swap = &simulation.swaps[0];
+        assert_eq!(swap.amount_out, amount_out);
+        assert_eq!(
+            reserves(&simulation.state),
+            (
+                reserve_0 + swap.amount_in.to::<u128>(),
+                reserve_1 - amount_out.to::<u128>()
+            )
+        );
+    }
+
+    #[test]
+    fn test_reserve_overflow() {
+        let state = state_space();
+        let routers = [SwapRouter::new(ROUTER, FACTORY, RouterKind::UniswapV2)];
+
+        // An amount in near U256::MAX must not overflow when added to the reserve
+        let input = IUniswapV2Router::swapExactTokensForTokensCall {
+            amountIn: U256::MAX,
+            amountOutMin: U256::ZERO,
+            path: vec![USDC, WETH],
+            to: Address::ZERO,
+            deadline: U256::MAX,
+        }
+        .abi_encode();
+
+        assert!(matches!(
+            simulate_pending_tx(
+                &state,
+                &routers,
+                &PendingTx::new(ROUTER, U256::ZERO, input.into())
+            ),
+            Err(PendingTxError::ReserveOverflow(pool)) if pool == POOL
+        ));
+    }
+
+    #[test]
+    fn test_find_pool() {
+        let simulation = PendingTxSimulation {
+            state: state_space(),
+            swaps: vec![],
+        };
+
+        let router = SwapRouter::new(ROUTER, FACTORY, RouterKind::UniswapV2);
+        let hop = simulation.find_pool(&router, WETH, USDC, None).unwrap();
+        assert_eq!(hop.pool, POOL);
+        assert_eq!((hop.token_in, hop.token_out), (WETH, USDC));
+
+        // The pools of a fork are at other addresses
+        let router = router.with_init_code_hash(B256::ZERO);
+        assert!(matches!(
+            simulation.find_pool(&router, WETH, USDC, None),
+            Err(PendingTxError::PoolNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unknown_target() {
+        assert!(matches!(
+            simulate_pending_tx(&state_space(), &[], &PendingTx::default()),
+            Err(PendingTxError::UnknownTarget(_))
+        ));
+    }
+}
@@ -0,0 +1,422 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use alloy::{
+    consensus::BlockHeader,
+    network::{BlockResponse, HeaderResponse, Network},
+    primitives::B256,
+    providers::Provider,
+    rpc::types::eth::{Filter, Log},
+};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    amm::AMM,
+    rpc::get_logs::{get_logs_in_chunks, LogRangeConfig, DEFAULT_MAX_CONCURRENT_REQUESTS},
+};
+
+use super::{
+    block_history::BlockHistory,
+    cache::StateChangeCache,
+    error::{ReplayError, StateSpaceError},
+    handle_block_state_changes_from_logs,
+    store::{StateSpaceBackend, StateSpaceStore},
+    unwind_state_changes, BlockStateUpdate, StateSnapshot,
+};
+
+/// A recorded block and the logs emitted in it, in log index order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayBlock {
+    pub number: u64,
+    pub hash: B256,
+    pub parent_hash: B256,
+    pub logs: Vec<Log>,
+}
+
+impl ReplayBlock {
+    pub fn new(number: u64, hash: B256, parent_hash: B256, logs: Vec<Log>) -> Self {
+        ReplayBlock {
+            number,
+            hash,
+            parent_hash,
+            logs,
+        }
+    }
+}
+
+/// Reads recorded blocks from a file with one JSON encoded [`ReplayBlock`] per line.
+pub fn read_replay_blocks(path: impl AsRef<Path>) -> Result<Vec<ReplayBlock>, ReplayError> {
+    let reader = BufReader::new(File::open(path)?);
+
+    let mut blocks = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            blocks.push(serde_json::from_str(&line)?);
+        }
+    }
+
+    Ok(blocks)
+}
+
+/// Writes `blocks` to a file with one JSON encoded [`ReplayBlock`] per line.
+pub fn write_replay_blocks(
+    path: impl AsRef<Path>,
+    blocks: &[ReplayBlock],
+) -> Result<(), ReplayError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    for block in blocks {
+        serde_json::to_writer(&mut writer, block)?;
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(writer.flush()?)
+}
+
+/// Records the blocks from `from_block` to `to_block` with the logs matching `filter`, fetched
+/// in ranges of `step` blocks.
+pub async fn record_blocks<N, P>(
+    filter: &Filter,
+    from_block: u64,
+    to_block: u64,
+    step: u64,
+    provider: P,
+) -> Result<Vec<ReplayBlock>, StateSpaceError<N>>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let mut logs = get_logs_in_chunks(
+        filter,
+        from_block,
+        to_block,
+        LogRangeConfig::new(step),
+        provider.clone(),
+    )
+    .await?
+    .into_iter()
+    .peekable();
+
+    // Headers are fetched concurrently and received in block order
+    let mut headers = stream::iter(from_block..=to_block)
+        .map(|block_number| {
+            let provider = provider.clone();
+            async move {
+                let block = provider
+                    .get_block_by_number(block_number.into())
+                    .await?
+                    .ok_or(StateSpaceError::BlockNumberNotFound)?;
+                let header = block.header();
+
+                Ok::<_, StateSpaceError<N>>((header.number(), header.hash(), header.parent_hash()))
+            }
+        })
+        .buffered(DEFAULT_MAX_CONCURRENT_REQUESTS);
+
+    let mut blocks = vec![];
+    while let Some(header) = headers.next().await {
+        let (block_number, block_hash, parent_hash) = header?;
+
+        let mut block_logs = vec![];
+        while let Some(log) = logs.next_if(|log| log.block_number == Some(block_number)) {
+            block_logs.push(log);
+        }
+
+        blocks.push(ReplayBlock::new(
+            block_number,
+            block_hash,
+            parent_hash,
+            block_logs,
+        ));
+    }
+
+    Ok(blocks)
+}
+
+/// Replays recorded blocks through the same state change handling and cache as the
+/// [`StateSpaceManager`](super::StateSpaceManager).
+///
+/// A block that does not build on the latest replayed block is handled as a reorg: the state
+/// changes after its parent are unwound from the cache before its logs are applied.
+#[derive(Debug)]
+pub struct StateSpaceReplay<const CAP: usize> {
+    state: StateSpaceStore,
+    state_change_cache: Arc<RwLock<StateChangeCache<CAP>>>,
+    block_history: BlockHistory,
+    latest_block: u64,
+}
+
+impl StateSpaceReplay<30> {
+    /// Creates a replay of the blocks after `latest_block`, at which `amms` were synced.
+    pub fn new(amms: Vec<AMM>, latest_block: u64) -> Self {
+        Self::new_with_capacity(amms, latest_block)
+    }
+}
+
+impl<const CAP: usize> StateSpaceReplay<CAP> {
+    pub fn new_with_capacity(amms: Vec<AMM>, latest_block: u64) -> Self {
+        Self {
+            state: StateSpaceStore::new(StateSpaceBackend::default(), amms.into()),
+            state_change_cache: Arc::new(RwLock::new(StateChangeCache::new())),
+            block_history: BlockHistory::new(CAP),
+            latest_block,
+        }
+    }
+
+    pub fn state(&self) -> StateSpaceStore {
+        self.state.clone()
+    }
+
+    /// Returns the latest replayed block.
+    pub fn latest_block(&self) -> u64 {
+        self.latest_block
+    }
+
+    /// Returns a snapshot of the state space at the latest replayed block.
+    pub async fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            block_number: self.latest_block,
+            amms: self.state.snapshot().await,
+        }
+    }
+
+    /// Applies a recorded block, returning the updates the manager would send for it.
+    ///
+    /// Blocks already replayed are skipped. If the block reorgs replayed blocks, an update for
+    /// the common ancestor restoring the unwound AMMs is returned first. The first replayed block
+    /// must directly follow the block the AMMs were synced at.
+    pub async fn replay_block<N: Network>(
+        &mut self,
+        block: ReplayBlock,
+    ) -> Result<Vec<BlockStateUpdate>, StateSpaceError<N>> {
+        if self.block_history.hash_at(block.number) == Some(block.hash) {
+            return Ok(vec![]);
+        }
+
+        let mut updates = vec![];
+
+        // The logs of skipped blocks would be missing from the state
+        if self.block_history.is_empty() && block.number != self.latest_block + 1 {
+            return Err(StateSpaceError::ReplayGap {
+                expected: self.latest_block + 1,
+                block_number: block.number,
+            });
+        }
+
+        // Recorded blocks must build on a replayed block, there is no provider to resync from
+        let parent_block_number = block.number.saturating_sub(1);
+        let parent_found = match self.block_history.hash_at(parent_block_number) {
+            Some(parent_hash) => parent_hash == block.parent_hash,
+            // The parent of the first replayed block is the block the AMMs were synced at
+            None => self.block_history.is_empty(),
+        };
+        if !parent_found {
+            return Err(StateSpaceError::ReplayParentNotFound {
+                block_number: block.number,
+                parent_hash: block.parent_hash,
+            });
+        }
+
+        // A block at or below the latest replayed block reorgs the blocks after its parent
+        let is_reorg = block.number <= self.latest_block;
+        if is_reorg {
+            let common_ancestor = parent_block_number;
+
+            let (_, unwound_amms) = unwind_state_changes(
+                self.state.clone(),
+                self.state_change_cache.clone(),
+                common_ancestor + 1,
+            )
+            .await?;
+
+            self.block_history.truncate_after(common_ancestor);
+
+            let mut update = BlockStateUpdate::new(common_ancestor, block.parent_hash);
+            update.updated = unwound_amms;
+            update.reorged = true;
+            updates.push(update);
+        }
+
+        // Recorded logs may omit their block number
+        let logs = block
+            .logs
+            .into_iter()
+            .map(|mut log| {
+                log.block_number.get_or_insert(block.number);
+                log.block_hash.get_or_insert(block.hash);
+                log
+            })
+            .collect();
+
+        for mut update in handle_block_state_changes_from_logs::<CAP, N>(
+            self.state.clone(),
+            self.state_change_cache.clone(),
+            logs,
+        )
+        .await?
+        {
+            update.reorged = is_reorg;
+            updates.push(update);
+        }
+
+        self.latest_block = block.number;
+        self.block_history.push(block.number, block.hash);
+
+        Ok(updates)
+    }
+
+    /// Applies each block in order, returning all updates.
+    pub async fn replay_blocks<N: Network>(
+        &mut self,
+        blocks: impl IntoIterator<Item = ReplayBlock>,
+    ) -> Result<Vec<BlockStateUpdate>, StateSpaceError<N>> {
+        let mut updates = vec![];
+        for block in blocks {
+            updates.extend(self.replay_block(block).await?);
+        }
+
+        Ok(updates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amm::uniswap_v2::UniswapV2Pool;
+    use alloy::{
+        network::Ethereum,
+        primitives::{address, keccak256, Address, LogData, U256},
+    };
+
+    const POOL: Address = address!("0000000000000000000000000000000000000001");
+
+    fn sync_log(reserve_0: u128, reserve_1: u128) -> Log {
+        let data = [U256::from(reserve_0), U256::from(reserve_1)]
+            .iter()
+            .flat_map(|word| word.to_be_bytes::<32>())
+            .collect::<Vec<u8>>();
+
+        Log {
+            inner: alloy::primitives::Log {
+                address: POOL,
+                data: LogData::new_unchecked(vec![keccak256("Sync(uint112,uint112)")], data.into()),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn block(number: u64, fork: u8, parent_fork: u8, logs: Vec<Log>) -> ReplayBlock {
+        ReplayBlock::new(
+            number,
+            B256::with_last_byte(number as u8 * 16 + fork),
+            B256::with_last_byte((number - 1) as u8 * 16 + parent_fork),
+            logs,
+        )
+    }
+
+    async fn reserves<const CAP: usize>(replay: &StateSpaceReplay<CAP>) -> (u128, u128) {
+        let Some(AMM::UniswapV2Pool(pool)) = replay.state().get(&POOL).await else {
+            panic!("missing pool");
+        };
+        (pool.reserve_0, pool.reserve_1)
+    }
+
+    #[tokio::test]
+    async fn test_replay_with_reorg() {
+        let amms = vec![AMM::UniswapV2Pool(UniswapV2Pool {
+            address: POOL,
+            ..Default::default()
+        })];
+        let mut replay = StateSpaceReplay::new(amms, 1);
+
+        let updates = replay
+            .replay_blocks::<Ethereum>([
+                block(2, 0, 0, vec![sync_log(1, 1)]),
+                block(3, 0, 0, vec![sync_log(2, 2)]),
+                block(4, 0, 0, vec![]),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(reserves(&replay).await, (2, 2));
+
+        // Block 3 is reorged out by a sibling building on block 2
+        let updates = replay
+            .replay_block::<Ethereum>(block(3, 1, 0, vec![sync_log(5, 5)]))
+            .await
+            .unwrap();
+        assert_eq!(updates.len(), 2);
+        assert!(updates.iter().all(|update| update.reorged));
+        assert_eq!(updates[0].block_number, 2);
+        assert_eq!(replay.latest_block(), 3);
+        assert_eq!(reserves(&replay).await, (5, 5));
+
+        // Reorging block 3 again restores the state at block 2
+        replay
+            .replay_block::<Ethereum>(block(3, 2, 0, vec![]))
+            .await
+            .unwrap();
+        assert_eq!(reserves(&replay).await, (1, 1));
+
+        // A block whose parent was never replayed cannot be applied
+        assert!(matches!(
+            replay
+                .replay_block::<Ethereum>(block(4, 0, 9, vec![]))
+                .await,
+            Err(StateSpaceError::ReplayParentNotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_replay_gap() {
+        let amms = vec![AMM::UniswapV2Pool(UniswapV2Pool {
+            address: POOL,
+            ..Default::default()
+        })];
+        let mut replay = StateSpaceReplay::new(amms, 1);
+
+        // Block 2 is missing from the recording
+        assert!(matches!(
+            replay
+                .replay_block::<Ethereum>(block(3, 0, 0, vec![sync_log(2, 2)]))
+                .await,
+            Err(StateSpaceError::ReplayGap {
+                expected: 2,
+                block_number: 3
+            })
+        ));
+        assert_eq!(replay.latest_block(), 1);
+
+        replay
+            .replay_block::<Ethereum>(block(2, 0, 0, vec![sync_log(1, 1)]))
+            .await
+            .unwrap();
+        assert_eq!(reserves(&replay).await, (1, 1));
+    }
+
+    #[test]
+    fn test_replay_file_roundtrip() {
+        let blocks = vec![block(2, 0, 0, vec![sync_log(1, 1)]), block(3, 0, 0, vec![])];
+        // Unique per run so that concurrent test runs do not share the file
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!(
+            "amms_replay_blocks_{}_{nanos}.jsonl",
+            std::process::id()
+        ));
+
+        write_replay_blocks(&path, &blocks).unwrap();
+        assert_eq!(read_replay_blocks(&path).unwrap(), blocks);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
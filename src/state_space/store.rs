@@ -0,0 +1,346 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use alloy::{
+    primitives::{Address, B256},
+    rpc::types::eth::Log,
+};
+use dashmap::DashMap;
+use tokio::sync::RwLock;
+
+use crate::{
+    amm::{balancer_v2::vault::vault_log_pool_address, AutomatedMarketMaker, AMM},
+    errors::AMMError,
+};
+
+use super::StateSpace;
+
+/// How the AMMs of a [`StateSpaceStore`] are stored and locked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StateSpaceBackend {
+    /// A persistent map behind a single `RwLock`.
+    ///
+    /// The logs of a block are applied under one write lock, so readers never see a partially
+    /// applied block, and snapshots are cheap.
+    #[default]
+    Locked,
+    /// A sharded concurrent map.
+    ///
+    /// Updating an AMM only locks the shard holding it, so readers of other AMMs are never
+    /// blocked. Readers may see a partially applied block and snapshots copy every AMM, use the
+    /// manager's snapshots for a consistent view.
+    Sharded,
+}
+
+/// The AMMs of a state space, shared between the manager and its readers.
+#[derive(Debug, Clone)]
+pub enum StateSpaceStore {
+    Locked(Arc<RwLock<StateSpace>>),
+    Sharded(Arc<DashMap<Address, AMM>>),
+}
+
+impl StateSpaceStore {
+    pub fn new(backend: StateSpaceBackend, state: StateSpace) -> Self {
+        match backend {
+            StateSpaceBackend::Locked => StateSpaceStore::Locked(Arc::new(RwLock::new(state))),
+            StateSpaceBackend::Sharded => {
+                StateSpaceStore::Sharded(Arc::new(state.0.into_iter().collect()))
+            }
+        }
+    }
+
+    pub fn backend(&self) -> StateSpaceBackend {
+        match self {
+            StateSpaceStore::Locked(_) => StateSpaceBackend::Locked,
+            StateSpaceStore::Sharded(_) => StateSpaceBackend::Sharded,
+        }
+    }
+
+    /// Returns a clone of the AMM at `address`.
+    pub async fn get(&self, address: &Address) -> Option<AMM> {
+        match self {
+            StateSpaceStore::Locked(state) => state.read().await.get(address).cloned(),
+            StateSpaceStore::Sharded(state) => state.get(address).map(|amm| amm.value().clone()),
+        }
+    }
+
+    pub async fn contains_key(&self, address: &Address) -> bool {
+        match self {
+            StateSpaceStore::Locked(state) => state.read().await.contains_key(address),
+            StateSpaceStore::Sharded(state) => state.contains_key(address),
+        }
+    }
+
+    pub async fn len(&self) -> usize {
+        match self {
+            StateSpaceStore::Locked(state) => state.read().await.len(),
+            StateSpaceStore::Sharded(state) => state.len(),
+        }
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Inserts `amm`, returning the AMM previously stored at its address.
+    pub async fn insert(&self, amm: AMM) -> Option<AMM> {
+        match self {
+            StateSpaceStore::Locked(state) => state.write().await.insert(amm.address(), amm),
+            StateSpaceStore::Sharded(state) => state.insert(amm.address(), amm),
+        }
+    }
+
+    /// Returns a clone of every AMM.
+    pub async fn amms(&self) -> Vec<AMM> {
+        match self {
+            StateSpaceStore::Locked(state) => state.read().await.values().cloned().collect(),
+            StateSpaceStore::Sharded(state) => {
+                state.iter().map(|amm| amm.value().clone()).collect()
+            }
+        }
+    }
+
+    /// Returns the event signatures that sync the stored AMMs.
+    pub async fn sync_event_signatures(&self) -> HashSet<B256> {
+        match self {
+            StateSpaceStore::Locked(state) => state
+                .read()
+                .await
+                .values()
+                .flat_map(|amm| amm.sync_on_event_signatures())
+                .collect(),
+            StateSpaceStore::Sharded(state) => state
+                .iter()
+                .flat_map(|amm| amm.value().sync_on_event_signatures())
+                .collect(),
+        }
+    }
+
+    /// Returns the stored AMMs as a [`StateSpace`].
+    ///
+    /// Cheap for the locked backend, copies every AMM for the sharded backend.
+    pub async fn snapshot(&self) -> StateSpace {
+        match self {
+            StateSpaceStore::Locked(state) => state.read().await.clone(),
+            StateSpaceStore::Sharded(state) => StateSpace(
+                state
+                    .iter()
+                    .map(|amm| (*amm.key(), amm.value().clone()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Syncs the stored AMMs from the logs of a single block.
+    ///
+    /// Returns the address, state before and state after the block of each AMM that changed, in
+    /// the order they were first updated.
+    pub async fn sync_from_logs(
+        &self,
+        logs: Vec<Log>,
+    ) -> Result<Vec<(Address, AMM, AMM)>, AMMError> {
+        // The state of each amm before the block, in the order they were first updated
+        let mut prev_state = vec![];
+        let mut prev_state_idx = HashMap::new();
+
+        let mut record_prev_state = |address: Address, amm: &AMM| {
+            prev_state_idx.entry(address).or_insert_with(|| {
+                prev_state.push(amm.clone());
+                prev_state.len() - 1
+            });
+        };
+
+        let updated = match self {
+            StateSpaceStore::Locked(state) => {
+                let mut state_writer = state.write().await;
+
+                for log in logs {
+                    let log_address = log_amm_address(&log);
+                    if let Some(amm) = state_writer.get_mut(&log_address) {
+                        record_prev_state(log_address, &*amm);
+                        amm.sync_from_log(log)?;
+                    }
+                }
+
+                prev_state
+                    .into_iter()
+                    .filter_map(|amm| {
+                        let address = amm.address();
+                        let updated_amm = state_writer.get(&address)?.clone();
+                        Some((address, amm, updated_amm))
+                    })
+                    .collect()
+            }
+            StateSpaceStore::Sharded(state) => {
+                for log in logs {
+                    let log_address = log_amm_address(&log);
+                    if let Some(mut amm) = state.get_mut(&log_address) {
+                        record_prev_state(log_address, &*amm);
+                        amm.sync_from_log(log)?;
+                    }
+                }
+
+                prev_state
+                    .into_iter()
+                    .filter_map(|amm| {
+                        let address = amm.address();
+                        let updated_amm = state.get(&address)?.value().clone();
+                        Some((address, amm, updated_amm))
+                    })
+                    .collect()
+            }
+        };
+
+        Ok(updated)
+    }
+
+    /// Replaces each AMM in `amms` by address, returning the address, replaced state and new state
+    /// of every AMM that was already stored.
+    pub async fn replace(&self, amms: Vec<AMM>) -> Vec<(Address, AMM, AMM)> {
+        let mut replaced = vec![];
+
+        match self {
+            StateSpaceStore::Locked(state) => {
+                let mut state_writer = state.write().await;
+                for amm in amms {
+                    let address = amm.address();
+                    if let Some(prev_amm) = state_writer.insert(address, amm.clone()) {
+                        replaced.push((address, prev_amm, amm));
+                    }
+                }
+            }
+            StateSpaceStore::Sharded(state) => {
+                for amm in amms {
+                    let address = amm.address();
+                    if let Some(prev_amm) = state.insert(address, amm.clone()) {
+                        replaced.push((address, prev_amm, amm));
+                    }
+                }
+            }
+        }
+
+        replaced
+    }
+}
+
+/// Returns the address of the AMM a log applies to.
+///
+/// Balancer V2 Vault logs apply to the pool they are emitted for, every other log applies to the
+/// contract that emitted it.
+fn log_amm_address(log: &Log) -> Address {
+    vault_log_pool_address(log).unwrap_or(log.address())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        amm::{
+            balancer_v2::vault::{BalancerV2VaultPool, IBalancerV2VaultPools},
+            uniswap_v2::UniswapV2Pool,
+        },
+        discovery::balancer_v2::BALANCER_V2_VAULT,
+    };
+    use alloy::{
+        primitives::{keccak256, LogData, U256},
+        sol_types::SolEvent,
+    };
+
+    fn sync_log(address: Address, reserve_0: u128, reserve_1: u128) -> Log {
+        let data = [U256::from(reserve_0), U256::from(reserve_1)]
+            .iter()
+            .flat_map(|word| word.to_be_bytes::<32>())
+            .collect::<Vec<u8>>();
+
+        Log {
+            inner: alloy::primitives::Log {
+                address,
+                data: LogData::new_unchecked(vec![keccak256("Sync(uint112,uint112)")], data.into()),
+            },
+            block_number: Some(1),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backends_sync_from_logs() {
+        let address = Address::with_last_byte(1);
+        let state: StateSpace = vec![AMM::UniswapV2Pool(UniswapV2Pool {
+            address,
+            ..Default::default()
+        })]
+        .into();
+
+        for backend in [StateSpaceBackend::Locked, StateSpaceBackend::Sharded] {
+            let store = StateSpaceStore::new(backend, state.clone());
+            assert_eq!(store.backend(), backend);
+
+            let logs = vec![
+                sync_log(address, 1, 2),
+                sync_log(Address::with_last_byte(2), 3, 4),
+                sync_log(address, 5, 6),
+            ];
+            let updated = store.sync_from_logs(logs).await.unwrap();
+
+            // Only the stored AMM is updated, once, with its state before the block
+            assert_eq!(updated.len(), 1);
+            let (updated_address, AMM::UniswapV2Pool(before), AMM::UniswapV2Pool(after)) =
+                &updated[0]
+            else {
+                panic!("unexpected AMM variant");
+            };
+            assert_eq!(*updated_address, address);
+            assert_eq!((before.reserve_0, before.reserve_1), (0, 0));
+            assert_eq!((after.reserve_0, after.reserve_1), (5, 6));
+
+            let Some(AMM::UniswapV2Pool(pool)) = store.snapshot().await.get(&address).cloned()
+            else {
+                panic!("missing pool in snapshot");
+            };
+            assert_eq!(pool.reserve_0, 5);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_vault_pool_from_logs() {
+        let mut pool = BalancerV2VaultPool {
+            address: Address::with_last_byte(1),
+            tokens: vec![Address::with_last_byte(2), Address::with_last_byte(3)],
+            balances: vec![U256::from(100), U256::from(100)],
+            ..Default::default()
+        };
+        pool.pool_id[..20].copy_from_slice(pool.address.as_slice());
+
+        let log = Log {
+            inner: alloy::primitives::Log {
+                address: BALANCER_V2_VAULT,
+                data: IBalancerV2VaultPools::Swap {
+                    poolId: pool.pool_id,
+                    tokenIn: pool.tokens[0],
+                    tokenOut: pool.tokens[1],
+                    amountIn: U256::from(10),
+                    amountOut: U256::from(9),
+                }
+                .encode_log_data(),
+            },
+            block_number: Some(1),
+            ..Default::default()
+        };
+
+        let store = StateSpaceStore::new(
+            StateSpaceBackend::Locked,
+            vec![AMM::BalancerV2VaultPool(pool.clone())].into(),
+        );
+
+        // Vault logs are applied to the pool they are emitted for
+        let updated = store.sync_from_logs(vec![log]).await.unwrap();
+        assert_eq!(updated.len(), 1);
+        let (address, _, AMM::BalancerV2VaultPool(after)) = &updated[0] else {
+            panic!("unexpected AMM variant");
+        };
+        assert_eq!(*address, pool.address);
+        assert_eq!(after.balances, vec![U256::from(110), U256::from(91)]);
+    }
+}
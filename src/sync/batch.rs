@@ -0,0 +1,143 @@
+use alloy::{network::Network, providers::Provider};
+
+use crate::{
+    amm::{balancer_v2, erc_4626, AutomatedMarketMaker, AMM},
+    errors::AMMError,
+};
+
+use super::events::{self, SyncEvent};
+
+/// Gas limit assumed for the `eth_call` executing a batch request, geth's default `--rpc.gascap`.
+pub const BATCH_REQUEST_GAS_LIMIT: u64 = 50_000_000;
+/// Batch request contracts return their data as deployed code, which is capped by EIP-170.
+pub const MAX_BATCH_RETURN_SIZE: usize = 24_576;
+
+/// Estimated cost of a single AMM within a batch request, used to size chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchCost {
+    /// Gas spent on the calls made for each AMM.
+    pub gas_per_amm: u64,
+    /// Size of the ABI encoded data returned for each AMM.
+    pub return_size_per_amm: usize,
+}
+
+/// 12 static calls per vault, 12 words returned.
+pub const ERC4626_BATCH_COST: BatchCost = BatchCost::new(250_000, 12 * 32);
+/// Pools with up to 8 tokens, returning 4 token-length arrays and a fee.
+pub const BALANCER_V2_BATCH_COST: BatchCost = BatchCost::new(400_000, 42 * 32);
+
+impl BatchCost {
+    pub const fn new(gas_per_amm: u64, return_size_per_amm: usize) -> Self {
+        BatchCost {
+            gas_per_amm,
+            return_size_per_amm,
+        }
+    }
+
+    /// Returns the largest number of AMMs that fits in one batch request under `gas_limit`.
+    pub fn chunk_size(&self, gas_limit: u64) -> usize {
+        let by_gas = (gas_limit / self.gas_per_amm.max(1)) as usize;
+        // Leave room for the offset and length of the returned array
+        let by_return_size = (MAX_BATCH_RETURN_SIZE - 64) / self.return_size_per_amm.max(1);
+
+        by_gas.min(by_return_size).max(1)
+    }
+}
+
+/// Populates ERC4626 vaults or Balancer pools with batch requests sized by [`BatchCost`].
+///
+/// If a batch request fails, the chunk size is halved and the chunk retried, so that AMMs
+/// exceeding the estimated cost do not fail the whole sync. The chunk size is reset after each
+/// successful request. An AMM failing on its own is skipped with a warning and left unpopulated,
+/// to be removed by [`filter_empty_amms`](crate::filters::filter_empty_amms).
+pub async fn populate_in_chunks<N, P>(
+    amms: &mut [AMM],
+    block_number: Option<u64>,
+    provider: P,
+) -> Result<(), AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let Some(amm) = amms.first() else {
+        return Ok(());
+    };
+
+    let cost = match amm {
+        AMM::ERC4626Vault(_) => ERC4626_BATCH_COST,
+        AMM::BalancerV2Pool(_) => BALANCER_V2_BATCH_COST,
+        _ => return Err(AMMError::IncongruentAMMs),
+    };
+
+    let max_chunk_size = cost.chunk_size(BATCH_REQUEST_GAS_LIMIT);
+    let mut chunk_size = max_chunk_size;
+    let mut chunk_start = 0;
+
+    while chunk_start < amms.len() {
+        let chunk_end = (chunk_start + chunk_size).min(amms.len());
+        let amm_chunk = &mut amms[chunk_start..chunk_end];
+
+        let result = match amm_chunk[0] {
+            AMM::ERC4626Vault(_) => {
+                erc_4626::batch_request::get_amm_data_batch_request(
+                    amm_chunk,
+                    block_number,
+                    provider.clone(),
+                )
+                .await
+            }
+            AMM::BalancerV2Pool(_) => {
+                balancer_v2::batch_request::get_amm_data_batch_request(
+                    amm_chunk,
+                    block_number,
+                    provider.clone(),
+                )
+                .await
+            }
+            _ => Err(AMMError::IncongruentAMMs),
+        };
+
+        match result {
+            Ok(()) => {
+                events::emit(SyncEvent::PopulateChunkSynced {
+                    amms: amm_chunk.len(),
+                });
+                chunk_start = chunk_end;
+                chunk_size = max_chunk_size;
+            }
+            Err(err) if chunk_size > 1 => {
+                chunk_size /= 2;
+                tracing::debug!(?err, chunk_size, "Batch request failed, shrinking chunk");
+            }
+            Err(err) => {
+                tracing::warn!(
+                    amm = ?amm_chunk[0].address(),
+                    ?err,
+                    "failed to populate AMM, skipping"
+                );
+                chunk_start = chunk_end;
+                chunk_size = max_chunk_size;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_size() {
+        assert_eq!(ERC4626_BATCH_COST.chunk_size(BATCH_REQUEST_GAS_LIMIT), 63);
+        assert_eq!(
+            BALANCER_V2_BATCH_COST.chunk_size(BATCH_REQUEST_GAS_LIMIT),
+            18
+        );
+
+        // A low gas limit takes precedence over the return size
+        assert_eq!(ERC4626_BATCH_COST.chunk_size(1_000_000), 4);
+        assert_eq!(ERC4626_BATCH_COST.chunk_size(0), 1);
+    }
+}
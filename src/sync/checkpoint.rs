@@ -5,7 +5,7 @@ use std::{
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use alloy::{network::Network, primitives::Address, providers::Provider};
+use alloy::{network::Network, providers::Provider};
 
 use serde::{Deserialize, Serialize};
 
@@ -13,16 +13,18 @@ use tokio::task::JoinHandle;
 
 use crate::{
     amm::{
-        factory::{AutomatedMarketMakerFactory, Factory},
-        uniswap_v2::factory::UniswapV2Factory,
-        uniswap_v3::factory::UniswapV3Factory,
         AMM,
+        factory::{AutomatedMarketMakerFactory, Factory},
     },
     errors::{AMMError, CheckpointError},
     filters,
+    tokens::TokenRegistry,
 };
 
-use super::amms_are_congruent;
+use super::{
+    events::{self, SyncEvent},
+    populate_amms,
+};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Checkpoint {
@@ -30,6 +32,9 @@ pub struct Checkpoint {
     pub block_number: u64,
     pub factories: Vec<Factory>,
     pub amms: Vec<AMM>,
+    /// Metadata of the tokens traded by `amms`
+    #[serde(default)]
+    pub tokens: TokenRegistry,
 }
 
 impl Checkpoint {
@@ -38,12 +43,14 @@ impl Checkpoint {
         block_number: u64,
         factories: Vec<Factory>,
         amms: Vec<AMM>,
+        tokens: TokenRegistry,
     ) -> Checkpoint {
         Checkpoint {
             timestamp,
             block_number,
             factories,
             amms,
+            tokens,
         }
     }
 }
@@ -64,50 +71,14 @@ where
     let checkpoint: Checkpoint =
         serde_json::from_str(read_to_string(&path_to_checkpoint)?.as_str())?;
 
-    // Sort all of the pools from the checkpoint into uniswap_v2_pools and uniswap_v3_pools pools so we can sync them concurrently
-    let (uniswap_v2_pools, uniswap_v3_pools, erc_4626_pools, balancer_v2_pools) =
-        sort_amms(checkpoint.amms);
-
     let mut aggregated_amms = vec![];
     let mut handles = vec![];
 
-    // Sync all uniswap v2 pools from checkpoint
-    if !uniswap_v2_pools.is_empty() {
+    // Sync all AMMs from the checkpoint, each variant is batched concurrently
+    if !checkpoint.amms.is_empty() {
         handles.push(
-            batch_sync_amms_from_checkpoint(
-                uniswap_v2_pools,
-                Some(current_block),
-                provider.clone(),
-            )
-            .await,
-        );
-    }
-
-    // Sync all uniswap v3 pools from checkpoint
-    if !uniswap_v3_pools.is_empty() {
-        handles.push(
-            batch_sync_amms_from_checkpoint(
-                uniswap_v3_pools,
-                Some(current_block),
-                provider.clone(),
-            )
-            .await,
-        );
-    }
-
-    if !erc_4626_pools.is_empty() {
-        // TODO: Batch sync erc4626 pools from checkpoint
-        todo!(
-            r#"""This function will produce an incorrect state if ERC4626 pools are present in the checkpoint. 
-            This logic needs to be implemented into batch_sync_amms_from_checkpoint"""#
-        );
-    }
-
-    if !balancer_v2_pools.is_empty() {
-        // TODO: Batch sync erc4626 pools from checkpoint
-        todo!(
-            r#"""This function will produce an incorrect state if Balancer v2 pools are present in the checkpoint. 
-            This logic needs to be implemented into batch_sync_amms_from_checkpoint"""#
+            batch_sync_amms_from_checkpoint(checkpoint.amms, Some(current_block), provider.clone())
+                .await,
         );
     }
 
@@ -137,14 +108,30 @@ where
         }
     }
 
+    // Register the tokens of new AMMs, best effort so that a failure does not discard the sync.
+    // Tokens left unregistered are retried on the next sync from the checkpoint.
+    let mut tokens = checkpoint.tokens;
+    if let Err(err) = tokens
+        .populate_from_amms(&aggregated_amms, Some(current_block), provider)
+        .await
+    {
+        tracing::warn!(?err, "failed to populate token metadata");
+    }
+
     // Update the sync checkpoint
-    construct_checkpoint(
+    construct_checkpoint_with_tokens(
         checkpoint.factories.clone(),
         &aggregated_amms,
+        tokens,
         current_block,
         path_to_checkpoint,
     )?;
 
+    events::emit(SyncEvent::SyncFinished {
+        amms: aggregated_amms.len(),
+        block_number: current_block,
+    });
+
     Ok((
         checkpoint.factories,
         aggregated_amms,
@@ -169,13 +156,25 @@ where
 
     for factory in factories.into_iter() {
         let provider = provider.clone();
+        let events = events::current_sender();
 
         // Spawn a new thread to get all pools and sync data for each dex
-        handles.push(tokio::spawn(async move {
+        handles.push(tokio::spawn(events::with_sender(events, async move {
+            events::emit(SyncEvent::FactoryStarted {
+                factory: factory.address(),
+                from_block,
+                to_block,
+            });
+
             let mut amms = factory
                 .get_all_pools_from_logs(from_block, to_block, step, provider.clone())
                 .await?;
 
+            events::emit(SyncEvent::AmmsDiscovered {
+                factory: factory.address(),
+                amms: amms.len(),
+            });
+
             factory
                 .populate_amm_data(&mut amms, Some(to_block), provider.clone())
                 .await?;
@@ -183,13 +182,21 @@ where
             // Clean empty pools
             amms = filters::filter_empty_amms(amms);
 
+            events::emit(SyncEvent::FactorySynced {
+                factory: factory.address(),
+                amms: amms.len(),
+            });
+
             Ok::<_, AMMError>(amms)
-        }));
+        })));
     }
 
     handles
 }
 
+/// Populates the AMMs from a checkpoint at `block_number`, or at the latest block if `None`.
+///
+/// `amms` may contain any mix of variants, see [`populate_amms`].
 pub async fn batch_sync_amms_from_checkpoint<N, P>(
     mut amms: Vec<AMM>,
     block_number: Option<u64>,
@@ -199,64 +206,21 @@ where
     N: Network,
     P: Provider<N> + Clone + 'static,
 {
-    let factory = match amms[0] {
-        AMM::UniswapV2Pool(_) => Some(Factory::UniswapV2Factory(UniswapV2Factory::new(
-            Address::ZERO,
-            0,
-            0,
-        ))),
-
-        AMM::UniswapV3Pool(_) => Some(Factory::UniswapV3Factory(UniswapV3Factory::new(
-            Address::ZERO,
-            0,
-        ))),
-
-        AMM::ERC4626Vault(_) => None,
-        AMM::BalancerV2Pool(_) => None,
-    };
+    let events = events::current_sender();
 
     // Spawn a new thread to get all pools and sync data for each dex
-    tokio::spawn(async move {
-        if let Some(factory) = factory {
-            if amms_are_congruent(&amms) {
-                // Get all pool data via batched calls
-                factory
-                    .populate_amm_data(&mut amms, block_number, provider)
-                    .await?;
-
-                // Clean empty pools
-                amms = filters::filter_empty_amms(amms);
-
-                Ok::<_, AMMError>(amms)
-            } else {
-                Err(AMMError::IncongruentAMMs)
-            }
-        } else {
-            Ok::<_, AMMError>(vec![])
-        }
-    })
-}
-
-pub fn sort_amms(amms: Vec<AMM>) -> (Vec<AMM>, Vec<AMM>, Vec<AMM>, Vec<AMM>) {
-    let mut uniswap_v2_pools = vec![];
-    let mut uniswap_v3_pools = vec![];
-    let mut erc_4626_vaults = vec![];
-    let mut balancer_v2_pools = vec![];
-    for amm in amms {
-        match amm {
-            AMM::UniswapV2Pool(_) => uniswap_v2_pools.push(amm),
-            AMM::UniswapV3Pool(_) => uniswap_v3_pools.push(amm),
-            AMM::ERC4626Vault(_) => erc_4626_vaults.push(amm),
-            AMM::BalancerV2Pool(_) => balancer_v2_pools.push(amm),
-        }
-    }
-
-    (
-        uniswap_v2_pools,
-        uniswap_v3_pools,
-        erc_4626_vaults,
-        balancer_v2_pools,
-    )
+    tokio::spawn(events::with_sender(events, async move {
+        let block_number = match block_number {
+            Some(block_number) => block_number,
+            None => provider.get_block_number().await?,
+        };
+
+        // Get all pool data via batched calls
+        populate_amms(&mut amms, block_number, provider).await?;
+
+        // Clean empty pools
+        Ok::<_, AMMError>(filters::filter_empty_amms(amms))
+    }))
 }
 
 pub async fn get_new_pools_from_range<N, P>(
@@ -276,13 +240,25 @@ where
 
     for factory in factories {
         let provider = provider.clone();
+        let events = events::current_sender();
 
         // Spawn a new thread to get all pools and sync data for each dex
-        handles.push(tokio::spawn(async move {
+        handles.push(tokio::spawn(events::with_sender(events, async move {
+            events::emit(SyncEvent::FactoryStarted {
+                factory: factory.address(),
+                from_block,
+                to_block,
+            });
+
             let mut pools = factory
                 .get_all_pools_from_logs(from_block, to_block, step, provider.clone())
                 .await?;
 
+            events::emit(SyncEvent::AmmsDiscovered {
+                factory: factory.address(),
+                amms: pools.len(),
+            });
+
             factory
                 .populate_amm_data(&mut pools, Some(to_block), provider.clone())
                 .await?;
@@ -290,8 +266,13 @@ where
             // Clean empty pools
             pools = filters::filter_empty_amms(pools);
 
+            events::emit(SyncEvent::FactorySynced {
+                factory: factory.address(),
+                amms: pools.len(),
+            });
+
             Ok::<_, AMMError>(pools)
-        }));
+        })));
     }
 
     handles
@@ -303,6 +284,26 @@ pub fn construct_checkpoint<P>(
     latest_block: u64,
     checkpoint_path: P,
 ) -> Result<(), CheckpointError>
+where
+    P: AsRef<Path>,
+{
+    construct_checkpoint_with_tokens(
+        factories,
+        amms,
+        TokenRegistry::new(),
+        latest_block,
+        checkpoint_path,
+    )
+}
+
+/// Writes a checkpoint like [`construct_checkpoint`], including the metadata of `tokens`.
+pub fn construct_checkpoint_with_tokens<P>(
+    factories: Vec<Factory>,
+    amms: &[AMM],
+    tokens: TokenRegistry,
+    latest_block: u64,
+    checkpoint_path: P,
+) -> Result<(), CheckpointError>
 where
     P: AsRef<Path>,
 {
@@ -311,6 +312,7 @@ where
         latest_block,
         factories,
         amms.to_vec(),
+        tokens,
     );
 
     std::fs::write(checkpoint_path, serde_json::to_string_pretty(&checkpoint)?)?;
@@ -326,3 +328,12 @@ where
     let checkpoint: Checkpoint = serde_json::from_str(read_to_string(checkpoint_path)?.as_str())?;
     Ok((checkpoint.amms, checkpoint.block_number))
 }
+
+/// Reads the token registry from a checkpoint, empty for checkpoints written without one.
+pub fn deconstruct_token_registry<P>(checkpoint_path: P) -> Result<TokenRegistry, CheckpointError>
+where
+    P: AsRef<Path>,
+{
+    let checkpoint: Checkpoint = serde_json::from_str(read_to_string(checkpoint_path)?.as_str())?;
+    Ok(checkpoint.tokens)
+}
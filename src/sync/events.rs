@@ -0,0 +1,128 @@
+use std::future::Future;
+
+use alloy::primitives::Address;
+use tokio::sync::mpsc::UnboundedSender;
+
+tokio::task_local! {
+    static SYNC_EVENTS: UnboundedSender<SyncEvent>;
+}
+
+/// Progress reported while syncing or discovering AMMs.
+///
+/// Events are only emitted for futures run through [`with_sync_events`]. Ranges are inclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncEvent {
+    /// A factory started syncing its AMMs up to `to_block`.
+    FactoryStarted {
+        factory: Address,
+        from_block: u64,
+        to_block: u64,
+    },
+    /// Logs for a block range have been fetched.
+    BlockRangeSynced {
+        from_block: u64,
+        to_block: u64,
+        logs: usize,
+    },
+    /// A range of pair indices has been fetched from a UniswapV2 factory.
+    PairIndicesSynced {
+        factory: Address,
+        synced: usize,
+        total: usize,
+    },
+    /// All AMMs created by a factory in the synced range have been found.
+    AmmsDiscovered { factory: Address, amms: usize },
+    /// A chunk of AMMs has been populated via a batch request.
+    PopulateChunkSynced { amms: usize },
+    /// A factory finished syncing, `amms` is the number of AMMs left after filtering.
+    FactorySynced { factory: Address, amms: usize },
+    /// A request failed with a transient error and will be retried.
+    RequestRetried {
+        from_block: u64,
+        to_block: u64,
+        attempt: u32,
+        error: String,
+    },
+    /// Factory discovery finished with `factories` matching the threshold.
+    FactoriesDiscovered { factories: usize },
+    /// Syncing finished at `block_number`.
+    SyncFinished { amms: usize, block_number: u64 },
+}
+
+/// Runs `future`, sending every [`SyncEvent`] it emits to `events`.
+///
+/// ```ignore
+/// let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+/// tokio::spawn(async move {
+///     while let Some(event) = rx.recv().await {
+///         println!("{event:?}");
+///     }
+/// });
+/// let (amms, block) = with_sync_events(tx, sync_amms(factories, provider, None, 500)).await?;
+/// ```
+pub async fn with_sync_events<F>(events: UnboundedSender<SyncEvent>, future: F) -> F::Output
+where
+    F: Future,
+{
+    SYNC_EVENTS.scope(events, future).await
+}
+
+/// Returns the event sender of the current task, if any.
+pub(crate) fn current_sender() -> Option<UnboundedSender<SyncEvent>> {
+    SYNC_EVENTS.try_with(|events| events.clone()).ok()
+}
+
+/// Runs `future` with the given event sender, used to carry events into spawned tasks.
+pub(crate) async fn with_sender<F>(
+    events: Option<UnboundedSender<SyncEvent>>,
+    future: F,
+) -> F::Output
+where
+    F: Future,
+{
+    match events {
+        Some(events) => SYNC_EVENTS.scope(events, future).await,
+        None => future.await,
+    }
+}
+
+/// Sends `event` to the current task's event channel, if any.
+pub(crate) fn emit(event: SyncEvent) {
+    // A closed receiver only means the caller stopped listening
+    let _ = SYNC_EVENTS.try_with(|events| events.send(event));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_events_propagate_to_spawned_tasks() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        with_sync_events(tx, async {
+            emit(SyncEvent::PopulateChunkSynced { amms: 1 });
+
+            let events = current_sender();
+            tokio::spawn(with_sender(events, async {
+                emit(SyncEvent::PopulateChunkSynced { amms: 2 });
+            }))
+            .await
+            .unwrap();
+        })
+        .await;
+
+        // Emitting outside of a scope is a no-op
+        emit(SyncEvent::PopulateChunkSynced { amms: 3 });
+
+        assert_eq!(
+            rx.recv().await,
+            Some(SyncEvent::PopulateChunkSynced { amms: 1 })
+        );
+        assert_eq!(
+            rx.recv().await,
+            Some(SyncEvent::PopulateChunkSynced { amms: 2 })
+        );
+        assert_eq!(rx.recv().await, None);
+    }
+}
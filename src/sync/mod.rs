@@ -1,18 +1,29 @@
+pub mod batch;
 pub mod checkpoint;
+pub mod events;
+pub mod pairs;
 
 use crate::{
     amm::{
-        AMM, AutomatedMarketMaker,
+        AMM, balancer_v2,
         factory::{AutomatedMarketMakerFactory, Factory},
         uniswap_v2, uniswap_v3,
     },
     errors::AMMError,
     filters,
+    tokens::TokenRegistry,
 };
 
-use std::panic::resume_unwind;
+use events::SyncEvent;
+
+use std::{
+    collections::HashMap,
+    mem::{self, Discriminant},
+    panic::resume_unwind,
+};
 
 use alloy::{network::Network, providers::Provider};
+use futures::future;
 
 /// Syncs all AMMs from the supplied factories.
 ///
@@ -42,15 +53,27 @@ where
     // For each dex supplied, get all pair created events and get reserve values
     for factory in factories.clone() {
         let provider = provider.clone();
+        let events = events::current_sender();
 
         // Spawn a new thread to get all pools and sync data for each dex
-        handles.push(tokio::spawn(async move {
+        handles.push(tokio::spawn(events::with_sender(events, async move {
             tracing::info!(?factory, "Getting all AMMs from factory");
+            events::emit(SyncEvent::FactoryStarted {
+                factory: factory.address(),
+                from_block: factory.creation_block(),
+                to_block: current_block,
+            });
+
             // Get all of the amms from the factory
             let mut amms = factory
                 .get_all_amms(Some(current_block), provider.clone(), step)
                 .await?;
 
+            events::emit(SyncEvent::AmmsDiscovered {
+                factory: factory.address(),
+                amms: amms.len(),
+            });
+
             tracing::info!(?factory, "Populating AMMs from factory");
             populate_amms(&mut amms, current_block, provider.clone()).await?;
 
@@ -58,7 +81,7 @@ where
             amms = filters::filter_empty_amms(amms);
 
             // If the factory is UniswapV2, set the fee for each pool according to the factory fee
-            if let Factory::UniswapV2Factory(factory) = factory {
+            if let Factory::UniswapV2Factory(ref factory) = factory {
                 for amm in amms.iter_mut() {
                     if let AMM::UniswapV2Pool(pool) = amm {
                         pool.fee = factory.fee;
@@ -66,8 +89,13 @@ where
                 }
             }
 
+            events::emit(SyncEvent::FactorySynced {
+                factory: factory.address(),
+                amms: amms.len(),
+            });
+
             Ok::<_, AMMError>(amms)
-        }));
+        })));
     }
 
     for handle in handles {
@@ -84,16 +112,32 @@ where
         }
     }
 
-    // Save a checkpoint if a path is provided
+    // Save a checkpoint with the tokens of the synced AMMs if a path is provided
     if let Some(checkpoint_path) = checkpoint_path {
-        checkpoint::construct_checkpoint(
+        let mut tokens = TokenRegistry::new();
+        // Best effort so that a failure does not discard the sync, tokens left unregistered are
+        // retried on the next sync from the checkpoint
+        if let Err(err) = tokens
+            .populate_from_amms(&aggregated_amms, Some(current_block), provider)
+            .await
+        {
+            tracing::warn!(?err, "failed to populate token metadata");
+        }
+
+        checkpoint::construct_checkpoint_with_tokens(
             factories,
             &aggregated_amms,
+            tokens,
             current_block,
             checkpoint_path,
         )?;
     }
 
+    events::emit(SyncEvent::SyncFinished {
+        amms: aggregated_amms.len(),
+        block_number: current_block,
+    });
+
     // Return the populated aggregated amms vec
     Ok((aggregated_amms, current_block))
 }
@@ -109,7 +153,10 @@ pub fn amms_are_congruent(amms: &[AMM]) -> bool {
     true
 }
 
-// Gets all pool data and sync reserves
+/// Gets all pool data and syncs reserves.
+///
+/// `amms` may contain any mix of variants. AMMs are grouped by variant and each group is populated
+/// concurrently via its batch request, with the results written back in place.
 pub async fn populate_amms<N, P>(
     amms: &mut [AMM],
     block_number: u64,
@@ -119,50 +166,98 @@ where
     N: Network,
     P: Provider<N> + Clone,
 {
+    if amms.is_empty() {
+        return Ok(());
+    }
+
     if amms_are_congruent(amms) {
-        match amms[0] {
-            AMM::UniswapV2Pool(_) => {
-                // Max batch size for call
-                let step = 127;
-                for amm_chunk in amms.chunks_mut(step) {
-                    uniswap_v2::batch_request::get_amm_data_batch_request(
-                        amm_chunk,
-                        provider.clone(),
-                    )
-                    .await?;
-                }
-            }
+        return populate_congruent_amms(amms, block_number, provider).await;
+    }
 
-            AMM::UniswapV3Pool(_) => {
-                // Max batch size for call
-                let step = 76;
-                for amm_chunk in amms.chunks_mut(step) {
-                    uniswap_v3::batch_request::get_amm_data_batch_request(
-                        amm_chunk,
-                        block_number,
-                        provider.clone(),
-                    )
-                    .await?;
-                }
+    // Group the AMMs by variant, keeping the index of each AMM to write the results back in place
+    let mut groups: HashMap<Discriminant<AMM>, (Vec<usize>, Vec<AMM>)> = HashMap::new();
+    for (idx, amm) in amms.iter().enumerate() {
+        let (indices, group) = groups.entry(mem::discriminant(amm)).or_default();
+        indices.push(idx);
+        group.push(amm.clone());
+    }
+
+    let populated_groups =
+        future::try_join_all(groups.into_values().map(|(indices, mut group)| {
+            let provider = provider.clone();
+            async move {
+                populate_congruent_amms(&mut group, block_number, provider).await?;
+                Ok::<_, AMMError>((indices, group))
             }
+        }))
+        .await?;
 
-            // TODO: Implement batch request
-            AMM::ERC4626Vault(_) => {
-                for amm in amms {
-                    amm.populate_data(None, provider.clone()).await?;
-                }
+    for (indices, group) in populated_groups {
+        for (idx, amm) in indices.into_iter().zip(group) {
+            amms[idx] = amm;
+        }
+    }
+
+    Ok(())
+}
+
+// Gets all pool data and sync reserves for AMMs of the same variant
+async fn populate_congruent_amms<N, P>(
+    amms: &mut [AMM],
+    block_number: u64,
+    provider: P,
+) -> Result<(), AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    match amms[0] {
+        AMM::UniswapV2Pool(_) => {
+            // Max batch size for call
+            let step = 127;
+            for amm_chunk in amms.chunks_mut(step) {
+                uniswap_v2::batch_request::get_amm_data_batch_request(
+                    amm_chunk,
+                    Some(block_number),
+                    provider.clone(),
+                )
+                .await?;
+
+                events::emit(SyncEvent::PopulateChunkSynced {
+                    amms: amm_chunk.len(),
+                });
             }
+        }
 
-            AMM::BalancerV2Pool(_) => {
-                for amm in amms {
-                    amm.populate_data(None, provider.clone()).await?;
-                }
+        AMM::UniswapV3Pool(_) => {
+            // Max batch size for call
+            let step = 76;
+            for amm_chunk in amms.chunks_mut(step) {
+                uniswap_v3::batch_request::get_amm_data_batch_request(
+                    amm_chunk,
+                    block_number,
+                    provider.clone(),
+                )
+                .await?;
+
+                events::emit(SyncEvent::PopulateChunkSynced {
+                    amms: amm_chunk.len(),
+                });
             }
         }
-    } else {
-        return Err(AMMError::IncongruentAMMs);
+
+        AMM::ERC4626Vault(_) | AMM::BalancerV2Pool(_) => {
+            batch::populate_in_chunks(amms, Some(block_number), provider).await?;
+        }
+
+        AMM::BalancerV2VaultPool(_) => {
+            // Multicalls are already split into batches
+            balancer_v2::vault::get_amm_data_batch_request(amms, Some(block_number), provider)
+                .await?;
+
+            events::emit(SyncEvent::PopulateChunkSynced { amms: amms.len() });
+        }
     }
 
-    // For each pair in the pairs vec, get the pool data
     Ok(())
 }
@@ -0,0 +1,253 @@
+use std::collections::HashSet;
+
+use alloy::{
+    network::Network,
+    primitives::{aliases::U24, Address, Bytes},
+    providers::Provider,
+    sol_types::SolCall,
+};
+use futures::future;
+
+use crate::{
+    amm::{
+        factory::{AutomatedMarketMakerFactory, Factory},
+        uniswap_v2::{factory::IUniswapV2Factory, UniswapV2Pool},
+        uniswap_v3::{
+            factory::{IUniswapV3Factory, UNISWAP_V3_FEE_TIERS},
+            UniswapV3Pool,
+        },
+        AMM,
+    },
+    errors::AMMError,
+    filters,
+    rpc::multicall,
+};
+
+/// A `getPair` or `getPool` lookup of a token pair on a factory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PoolLookup {
+    /// Index of the factory in the factories being queried
+    factory_idx: usize,
+    token_a: Address,
+    token_b: Address,
+    /// Fee tier for Uniswap V3 factories
+    fee: Option<u32>,
+}
+
+impl PoolLookup {
+    fn call_data(&self) -> Bytes {
+        match self.fee {
+            Some(fee) => IUniswapV3Factory::getPoolCall {
+                tokenA: self.token_a,
+                tokenB: self.token_b,
+                fee: U24::from(fee),
+            }
+            .abi_encode()
+            .into(),
+            None => IUniswapV2Factory::getPairCall {
+                tokenA: self.token_a,
+                tokenB: self.token_b,
+            }
+            .abi_encode()
+            .into(),
+        }
+    }
+
+    /// Returns the pool found by the lookup, or `None` if the call reverted or found no pool.
+    fn decode_pool(&self, return_data: Option<Bytes>) -> Option<Address> {
+        let return_data = return_data?;
+        let pool = match self.fee {
+            Some(_) => {
+                IUniswapV3Factory::getPoolCall::abi_decode_returns(&return_data, true)
+                    .ok()?
+                    .pool
+            }
+            None => {
+                IUniswapV2Factory::getPairCall::abi_decode_returns(&return_data, true)
+                    .ok()?
+                    .pair
+            }
+        };
+
+        (!pool.is_zero()).then_some(pool)
+    }
+}
+
+/// Returns a lookup for each token pair on each factory that can be queried by pair, one per fee
+/// tier for Uniswap V3 factories.
+fn pool_lookups(pairs: &[(Address, Address)], factories: &[Factory]) -> Vec<PoolLookup> {
+    let mut lookups = vec![];
+
+    for (factory_idx, factory) in factories.iter().enumerate() {
+        for &(token_a, token_b) in pairs {
+            let lookup = PoolLookup {
+                factory_idx,
+                token_a,
+                token_b,
+                fee: None,
+            };
+
+            match factory {
+                Factory::UniswapV2Factory(_) => lookups.push(lookup),
+                Factory::UniswapV3Factory(_) => {
+                    lookups.extend(UNISWAP_V3_FEE_TIERS.iter().map(|&fee| PoolLookup {
+                        fee: Some(fee),
+                        ..lookup
+                    }))
+                }
+                // Balancer pools are not indexed by token pair
+                Factory::BalancerV2Factory(_) => {}
+            }
+        }
+    }
+
+    lookups
+}
+
+/// Returns an empty AMM of the factory's type at `address`.
+fn empty_amm(factory: &Factory, address: Address, fee: Option<u32>) -> Option<AMM> {
+    match factory {
+        Factory::UniswapV2Factory(factory) => Some(AMM::UniswapV2Pool(UniswapV2Pool {
+            address,
+            factory_address: Some(factory.address),
+            fee: factory.fee,
+            ..Default::default()
+        })),
+        Factory::UniswapV3Factory(factory) => Some(AMM::UniswapV3Pool(UniswapV3Pool {
+            address,
+            factory_address: Some(factory.address),
+            fee: fee?,
+            ..Default::default()
+        })),
+        Factory::BalancerV2Factory(_) => None,
+    }
+}
+
+/// Gets the populated pools of `token_a` and `token_b` on each of the `factories`.
+///
+/// See [`get_amms_for_pairs`].
+pub async fn get_amms_for_pair<N, P>(
+    token_a: Address,
+    token_b: Address,
+    factories: &[Factory],
+    block_number: Option<u64>,
+    provider: P,
+) -> Result<Vec<AMM>, AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    get_amms_for_pairs(&[(token_a, token_b)], factories, block_number, provider).await
+}
+
+/// Gets the populated pools of each token pair on each of the `factories`, without scanning logs.
+///
+/// Every pair is looked up with `getPair` on Uniswap V2 factories and `getPool` for each of the
+/// [`UNISWAP_V3_FEE_TIERS`] on Uniswap V3 factories, in a single batch of calls through Multicall3.
+/// The pools found are populated with the batch request of their factory at `block_number`, or
+/// the latest block if `None`, and pools that could not be populated are removed. Balancer
+/// factories cannot be queried by pair and are skipped.
+pub async fn get_amms_for_pairs<N, P>(
+    pairs: &[(Address, Address)],
+    factories: &[Factory],
+    block_number: Option<u64>,
+    provider: P,
+) -> Result<Vec<AMM>, AMMError>
+where
+    N: Network,
+    P: Provider<N> + Clone,
+{
+    let block_number = match block_number {
+        Some(block_number) => block_number,
+        None => provider.get_block_number().await?,
+    };
+
+    let lookups = pool_lookups(pairs, factories);
+    let calls = lookups
+        .iter()
+        .map(|lookup| (factories[lookup.factory_idx].address(), lookup.call_data()))
+        .collect();
+    let return_data = multicall::try_aggregate(calls, Some(block_number), provider.clone()).await?;
+
+    // Group the pools found by factory, pairs may be listed in both token orders
+    let mut seen = HashSet::new();
+    let mut amms_by_factory = vec![vec![]; factories.len()];
+    for (lookup, return_data) in lookups.into_iter().zip(return_data) {
+        let Some(pool) = lookup.decode_pool(return_data) else {
+            continue;
+        };
+
+        if seen.insert(pool) {
+            if let Some(amm) = empty_amm(&factories[lookup.factory_idx], pool, lookup.fee) {
+                amms_by_factory[lookup.factory_idx].push(amm);
+            }
+        }
+    }
+
+    let populated = future::try_join_all(factories.iter().zip(amms_by_factory).map(
+        |(factory, mut amms)| {
+            let provider = provider.clone();
+            async move {
+                if !amms.is_empty() {
+                    factory
+                        .populate_amm_data(&mut amms, Some(block_number), provider)
+                        .await?;
+                }
+
+                Ok::<_, AMMError>(amms)
+            }
+        },
+    ))
+    .await?;
+
+    Ok(filters::filter_empty_amms(
+        populated.into_iter().flatten().collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amm::{
+        balancer_v2::factory::BalancerV2Factory, uniswap_v2::factory::UniswapV2Factory,
+        uniswap_v3::factory::UniswapV3Factory,
+    };
+    use alloy::sol_types::SolValue;
+
+    #[test]
+    fn test_pool_lookups() {
+        let pairs = [
+            (Address::with_last_byte(1), Address::with_last_byte(2)),
+            (Address::with_last_byte(1), Address::with_last_byte(3)),
+        ];
+        let factories = [
+            Factory::UniswapV2Factory(UniswapV2Factory::new(Address::with_last_byte(10), 0, 300)),
+            Factory::UniswapV3Factory(UniswapV3Factory::new(Address::with_last_byte(11), 0)),
+            Factory::BalancerV2Factory(BalancerV2Factory::default()),
+        ];
+
+        let lookups = pool_lookups(&pairs, &factories);
+        assert_eq!(
+            lookups.len(),
+            pairs.len() * (1 + UNISWAP_V3_FEE_TIERS.len())
+        );
+        assert!(lookups[..2].iter().all(|lookup| lookup.fee.is_none()));
+        assert_eq!(lookups[2].fee, Some(UNISWAP_V3_FEE_TIERS[0]));
+
+        // A factory returning the zero address has no pool for the pair
+        let zero_address = Some(Bytes::from(Address::ZERO.abi_encode()));
+        assert_eq!(lookups[0].decode_pool(zero_address), None);
+        assert_eq!(lookups[0].decode_pool(None), None);
+
+        let pool = Address::with_last_byte(20);
+        let return_data = Some(Bytes::from(pool.abi_encode()));
+        assert_eq!(lookups[2].decode_pool(return_data), Some(pool));
+
+        let Some(AMM::UniswapV3Pool(v3_pool)) = empty_amm(&factories[1], pool, lookups[2].fee)
+        else {
+            panic!("expected a Uniswap V3 pool");
+        };
+        assert_eq!(v3_pool.fee, UNISWAP_V3_FEE_TIERS[0]);
+        assert_eq!(v3_pool.factory_address, Some(Address::with_last_byte(11)));
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use alloy::{
+    network::Network,
+    primitives::{Address, Bytes, U256},
+    providers::Provider,
+    sol_types::{SolCall, SolValue},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    amm::{AutomatedMarketMaker, IErc20, AMM},
+    errors::AMMError,
+    rpc::multicall,
+};
+
+/// Number of calls made to each token to get its metadata.
+const METADATA_CALLS: usize = 4;
+
+/// Deviations of a token from the ERC20 metadata standard.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenFlags {
+    /// `symbol()` or `name()` returns `bytes32` instead of `string`, e.g. MKR
+    pub bytes32_metadata: bool,
+    /// `symbol()` or `name()` reverted or returned invalid data
+    pub missing_metadata: bool,
+    /// `decimals()` reverted or returned invalid data, the decimals are set to zero
+    pub missing_decimals: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Token {
+    pub address: Address,
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u8,
+    pub total_supply: U256,
+    #[serde(default)]
+    pub flags: TokenFlags,
+}
+
+impl Token {
+    /// Returns whether the token implements the ERC20 metadata functions as specified.
+    pub fn is_standard(&self) -> bool {
+        self.flags == TokenFlags::default()
+    }
+
+    /// Builds the token from the return data of the calls made by [`metadata_calls`].
+    fn from_return_data(address: Address, return_data: &[Option<Bytes>; METADATA_CALLS]) -> Self {
+        let [symbol, name, decimals, total_supply] = return_data;
+        let mut flags = TokenFlags::default();
+
+        let mut decode_metadata = |return_data: &Option<Bytes>| match return_data
+            .as_ref()
+            .and_then(|data| decode_string_or_bytes32(data))
+        {
+            Some((value, is_bytes32)) => {
+                flags.bytes32_metadata |= is_bytes32;
+                value
+            }
+            None => {
+                flags.missing_metadata = true;
+                String::new()
+            }
+        };
+        let symbol = decode_metadata(symbol);
+        let name = decode_metadata(name);
+
+        let decimals = match decimals
+            .as_ref()
+            .and_then(|data| IErc20::decimalsCall::abi_decode_returns(data, true).ok())
+        {
+            Some(IErc20::decimalsReturn { _0: decimals }) => decimals,
+            None => {
+                flags.missing_decimals = true;
+                0
+            }
+        };
+
+        let total_supply = total_supply
+            .as_ref()
+            .and_then(|data| IErc20::totalSupplyCall::abi_decode_returns(data, true).ok())
+            .map(|total_supply| total_supply._0)
+            .unwrap_or_default();
+
+        Token {
+            address,
+            symbol,
+            name,
+            decimals,
+            total_supply,
+            flags,
+        }
+    }
+}
+
+/// Decodes a `string` return value, falling back to a `bytes32` one padded with zeros.
+///
+/// Returns the value and whether it was a `bytes32`.
+fn decode_string_or_bytes32(return_data: &[u8]) -> Option<(String, bool)> {
+    if let Ok(value) = String::abi_decode(return_data, true) {
+        return Some((value, false));
+    }
+
+    if return_data.len() != 32 {
+        return None;
+    }
+
+    let len = return_data
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(return_data.len());
+    let value = String::from_utf8(return_data[..len].to_vec()).ok()?;
+
+    Some((value, true))
+}
+
+/// Returns the calls getting the metadata of `token`, in the order expected by
+/// [`Token::from_return_data`].
+fn metadata_calls(token: Address) -> [(Address, Bytes); METADATA_CALLS] {
+    [
+        IErc20::symbolCall {}.abi_encode(),
+        IErc20::nameCall {}.abi_encode(),
+        IErc20::decimalsCall {}.abi_encode(),
+        IErc20::totalSupplyCall {}.abi_encode(),
+    ]
+    .map(|call_data| (token, call_data.into()))
+}
+
+/// Metadata of the tokens traded by a set of AMMs, keyed by token address.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<Token>", into = "Vec<Token>")]
+pub struct TokenRegistry {
+    tokens: HashMap<Address, Token>,
+}
+
+impl TokenRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, address: &Address) -> Option<&Token> {
+        self.tokens.get(address)
+    }
+
+    pub fn contains(&self, address: &Address) -> bool {
+        self.tokens.contains_key(address)
+    }
+
+    /// Returns the decimals of the token at `address`, if it is registered.
+    pub fn decimals(&self, address: &Address) -> Option<u8> {
+        self.tokens.get(address).map(|token| token.decimals)
+    }
+
+    /// Inserts `token`, returning the token previously registered at its address.
+    pub fn insert(&mut self, token: Token) -> Option<Token> {
+        self.tokens.insert(token.address, token)
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &Token> {
+        self.tokens.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Registers every token in `tokens` that is not registered yet, getting their metadata at
+    /// `block_number`, or at the latest block if `None`, in batches of calls through Multicall3.
+    ///
+    /// Tokens whose decimals cannot be read are left unregistered, so that they are retried by
+    /// the next populate.
+    pub async fn populate<N, P>(
+        &mut self,
+        tokens: impl IntoIterator<Item = Address>,
+        block_number: Option<u64>,
+        provider: P,
+    ) -> Result<(), AMMError>
+    where
+        N: Network,
+        P: Provider<N> + Clone,
+    {
+        let mut missing = tokens
+            .into_iter()
+            .filter(|token| !token.is_zero() && !self.contains(token))
+            .collect::<Vec<_>>();
+        missing.sort();
+        missing.dedup();
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let calls = missing
+            .iter()
+            .flat_map(|token| metadata_calls(*token))
+            .collect();
+        let return_data = multicall::try_aggregate(calls, block_number, provider).await?;
+
+        for (address, return_data) in missing
+            .into_iter()
+            .zip(return_data.chunks_exact(METADATA_CALLS))
+        {
+            let return_data = return_data.try_into().expect("Chunks should be exact");
+            let token = Token::from_return_data(address, return_data);
+            // Registering a token without decimals would prevent getting them on a later populate
+            if token.flags.missing_decimals {
+                tracing::debug!(?address, "token decimals unavailable, not registering");
+                continue;
+            }
+            if !token.is_standard() {
+                tracing::debug!(?address, flags = ?token.flags, "non-standard token");
+            }
+
+            self.insert(token);
+        }
+
+        Ok(())
+    }
+
+    /// Registers every token traded by `amms` that is not registered yet, see
+    /// [`populate`](Self::populate).
+    pub async fn populate_from_amms<N, P>(
+        &mut self,
+        amms: &[AMM],
+        block_number: Option<u64>,
+        provider: P,
+    ) -> Result<(), AMMError>
+    where
+        N: Network,
+        P: Provider<N> + Clone,
+    {
+        let tokens = amms.iter().flat_map(|amm| amm.tokens()).collect::<Vec<_>>();
+        self.populate(tokens, block_number, provider).await
+    }
+}
+
+impl From<Vec<Token>> for TokenRegistry {
+    fn from(tokens: Vec<Token>) -> Self {
+        TokenRegistry {
+            tokens: tokens
+                .into_iter()
+                .map(|token| (token.address, token))
+                .collect(),
+        }
+    }
+}
+
+impl From<TokenRegistry> for Vec<Token> {
+    fn from(registry: TokenRegistry) -> Self {
+        let mut tokens = registry.tokens.into_values().collect::<Vec<_>>();
+        tokens.sort_by_key(|token| token.address);
+        tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::B256;
+
+    #[test]
+    fn test_token_from_return_data() {
+        let address = Address::with_last_byte(1);
+        let return_data = [
+            Some(Bytes::from("WETH".to_string().abi_encode())),
+            Some(Bytes::from("Wrapped Ether".to_string().abi_encode())),
+            Some(Bytes::from(U256::from(18).abi_encode())),
+            Some(Bytes::from(U256::from(1000).abi_encode())),
+        ];
+
+        let token = Token::from_return_data(address, &return_data);
+        assert_eq!(token.symbol, "WETH");
+        assert_eq!(token.name, "Wrapped Ether");
+        assert_eq!(token.decimals, 18);
+        assert_eq!(token.total_supply, U256::from(1000));
+        assert!(token.is_standard());
+
+        // MKR returns its symbol and name as bytes32
+        let mut symbol = B256::ZERO;
+        symbol[..3].copy_from_slice(b"MKR");
+        let return_data = [
+            Some(Bytes::from(symbol.to_vec())),
+            Some(Bytes::from("Maker".to_string().abi_encode())),
+            None,
+            None,
+        ];
+
+        let token = Token::from_return_data(address, &return_data);
+        assert_eq!(token.symbol, "MKR");
+        assert_eq!(token.decimals, 0);
+        assert!(token.flags.bytes32_metadata);
+        assert!(token.flags.missing_decimals);
+        assert!(!token.flags.missing_metadata);
+    }
+
+    #[test]
+    fn test_token_registry_serde() {
+        let mut registry = TokenRegistry::new();
+        registry.insert(Token {
+            address: Address::with_last_byte(1),
+            symbol: "WETH".to_string(),
+            decimals: 18,
+            ..Default::default()
+        });
+
+        let serialized = serde_json::to_string(&registry).unwrap();
+        let deserialized: TokenRegistry = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, registry);
+        assert_eq!(deserialized.decimals(&Address::with_last_byte(1)), Some(18));
+    }
+}